@@ -6,11 +6,13 @@ use bevy::prelude::*;
 use bevy_quinnet::client::certificate::CertificateVerificationMode;
 use bevy_quinnet::client::connection::ConnectionConfiguration;
 use bevy_quinnet::client::{QuinnetClientPlugin, Client};
-use ndarray::{Array2, Array1};
+use ndarray::Array1;
 
 use crate::communication::shared::messages::{ServerMessage, EntityId, CellParams, CellState, Tick};
-use crate::game_logic::cell::{spawn_cell, CellSpawnEvent, FlagellumSpawnEvent, EyeSpawnEvent, CellDespawnEvent, despawn_cell, FoodSpawnEvent, spawn_food, FoodDespawnEvent, despawn_food, Cell, DelayedDespawnQueue, Energy};
+use crate::game_logic::cell::{SimulationTick, spawn_cell, CellSpawnEvent, FlagellumSpawnEvent, EyeSpawnEvent, CellDespawnEvent, despawn_cell, FoodSpawnEvent, spawn_food, FoodDespawnEvent, despawn_food, Cell, DelayedDespawnQueue, Energy, CellCount, Genome};
 use crate::game_logic::physics::{Velocity, Force, AngularVelocity, AngularForce};
+use crate::game_logic::config::SimulationConfig;
+use crate::game_logic::obstacle::place_obstacles;
 use crate::game_logic::sprites::*;
 
 pub struct ClientPlugin;
@@ -60,7 +62,7 @@ fn add_ticks_to_cells(
 
 fn read_messages(
     mut commands: Commands,
-    mut despawn_queue: ResMut<DelayedDespawnQueue>,
+    (mut despawn_queue, mut cell_count, mut config, mut simulation_tick): (ResMut<DelayedDespawnQueue>, ResMut<CellCount>, ResMut<SimulationConfig>, ResMut<SimulationTick>),
     mut client: ResMut<Client>,
     mut entity_map: ResMut<EntityMap>,
     mut cell_spawn_event_writer: EventWriter<CellSpawnEvent>,
@@ -101,12 +103,14 @@ fn read_messages(
                 light_sprite.as_deref(),
                 flagellum_sprite.as_deref(),
                 eye_sprite.as_deref(),
+                &mut cell_count,
             ),
             ServerMessage::CellDespawn(entity) => cell_despawn_handler(
                 &mut despawn_queue, 
                 &mut entity_map, 
                 &mut cell_despawn_event_writer, 
-                entity
+                entity,
+                &mut cell_count,
             ),
             ServerMessage::FoodSpawn(entity, position) => food_spawn_handler(
                 &mut commands, 
@@ -122,6 +126,12 @@ fn read_messages(
                 &mut food_despawn_event_writer, 
                 entity,
             ),
+            ServerMessage::World(world) => config.world = world,
+            ServerMessage::Light(light) => config.light = light,
+            // the light follows the server's clock
+            ServerMessage::Clock(tick) => **simulation_tick = *tick,
+            // the world shape arrives first, obstacles are placed into it like on the server
+            ServerMessage::Obstacles(obstacles) => place_obstacles(&mut commands, &obstacles, &config.world.shape),
         }
     }
 }
@@ -140,10 +150,18 @@ fn cell_spawn_handler(
     light_sprite: Option<&LightSprite>,
     flagellum_sprite: Option<&FlagellumSprite>,
     eye_sprite: Option<&EyeSprite>,
+    cell_count: &mut CellCount,
 ) {
     if entity_map.contains_key(&entity) {
         return;
     }
+    // clients only simulate the body, the brain stays on the server
+    let genome = Genome {
+        chloroplasts: cell_params.chloroplasts,
+        flagella: cell_params.flagella_params,
+        eyes: cell_params.eye_params,
+        ..default()
+    };
     entity_map.insert(entity,
         spawn_cell(commands, 
             cell_spawn_event_writer, 
@@ -152,17 +170,13 @@ fn cell_spawn_handler(
             cell_state.position.extend(0.),
             Quat::from_rotation_z(cell_state.rotation),
             cell_state.energy,
-            todo!(),
-            todo!(),
-            cell_params.flagella_params,
-            cell_params.eye_params,
-            Array2::default((0,0)),
-            Array1::default(0),
-            Array1::default(0),
+            genome,
+            None,
             cell_sprite,
             light_sprite,
             flagellum_sprite,
             eye_sprite,
+            cell_count,
         )
     );
 }
@@ -172,9 +186,10 @@ fn cell_despawn_handler(
     entity_map: &mut EntityMap,
     cell_despawn_event_writer: &mut EventWriter<CellDespawnEvent>,
     entity: EntityId,
+    cell_count: &mut CellCount,
 ) {
     if let Some(cell_entity) = entity_map.remove(&entity) {
-        despawn_cell(despawn_queue, cell_despawn_event_writer, cell_entity);
+        despawn_cell(despawn_queue, cell_despawn_event_writer, cell_entity, cell_count);
     }
 }
 
@@ -195,6 +210,7 @@ fn food_spawn_handler(
             commands, 
             food_spawn_event_writer, 
             position.extend(0.),
+            0.,
             food_sprite,
             light_sprite,
         )
@@ -8,9 +8,11 @@ use bevy_quinnet::server::{QuinnetServerPlugin, Server, ServerConfiguration, Con
 use bevy_quinnet::shared::QuinnetError;
 use bevy_quinnet::shared::channel::ChannelId;
 
-use crate::communication::shared::messages::ServerMessage;
-use crate::game_logic::cell::{Cell, CellDespawnEvent, Food, FoodDespawnEvent, FlagellaParams, EyeParams, Energy};
+use crate::communication::shared::messages::{ServerMessage, Tick};
+use crate::game_logic::cell::{Cell, CellDespawnEvent, Food, FoodDespawnEvent, Genome, Energy, SimulationTick};
 use crate::game_logic::physics::{Velocity, Force, AngularVelocity, AngularForce};
+use crate::game_logic::config::SimulationConfig;
+use crate::game_logic::obstacle::ObstacleMap;
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct TickCounter(u64);
@@ -88,16 +90,23 @@ fn send_reliable_messages(
 
 fn connect_event_handler(
     mut message_queue: ResMut<MessageQueue>,
-    cell_query: Query<(Entity, &FlagellaParams, &EyeParams, &Transform, &Velocity, &Force, &AngularVelocity, &AngularForce, &Energy), With<Cell>>,
+    cell_query: Query<(Entity, &Genome, &Transform, &Velocity, &Force, &AngularVelocity, &AngularForce, &Energy), With<Cell>>,
     food_query: Query<(Entity, &Transform), With<Food>>,
     mut event_reader: EventReader<ConnectionEvent>,
+    config: Res<SimulationConfig>,
+    map: Res<ObstacleMap>,
+    simulation_tick: Res<SimulationTick>,
 ) {
     for ConnectionEvent{id} in event_reader.iter() {
         info!("Client id {} connected.", id);
-        for (entity, flagella_params, eye_params, transform, velocity, force, ang_velocity, ang_force, energy) in cell_query.iter() {
+        message_queue.add(Recipient::User(*id), ServerMessage::World(config.world.clone()));
+        message_queue.add(Recipient::User(*id), ServerMessage::Light(config.light.clone()));
+        message_queue.add(Recipient::User(*id), ServerMessage::Clock(Tick::new(**simulation_tick)));
+        message_queue.add(Recipient::User(*id), ServerMessage::Obstacles(map.obstacles.clone()));
+        for (entity, genome, transform, velocity, force, ang_velocity, ang_force, energy) in cell_query.iter() {
             message_queue.add(
                 Recipient::User(*id), 
-                ServerMessage::cell_spawn(entity, flagella_params, eye_params, transform, *velocity, *force, *ang_velocity, *ang_force, *energy)
+                ServerMessage::cell_spawn(entity, genome, transform, *velocity, *force, *ang_velocity, *ang_force, *energy)
             );
         }
         for (food_entity, food_transform) in food_query.iter() {
@@ -111,13 +120,13 @@ fn connect_event_handler(
 
 fn cell_spawn_handler(
     mut message_queue: ResMut<MessageQueue>,
-    new_cell_query: Query<(Entity, &FlagellaParams, &EyeParams, &Transform, &Velocity, &Force, &AngularVelocity, &AngularForce, &Energy), Added<Cell>>,
+    new_cell_query: Query<(Entity, &Genome, &Transform, &Velocity, &Force, &AngularVelocity, &AngularForce, &Energy), Added<Cell>>,
     mut despawn_event_reader: EventReader<CellDespawnEvent>,
 ) {
-    for (entity, flagella_params, eye_params, transform, velocity, force, ang_velocity, ang_force, energy) in new_cell_query.iter() {
+    for (entity, genome, transform, velocity, force, ang_velocity, ang_force, energy) in new_cell_query.iter() {
         message_queue.add(
             Recipient::Broadcast, 
-            ServerMessage::cell_spawn(entity, flagella_params, eye_params, transform, *velocity, *force, *ang_velocity, *ang_force, *energy)
+            ServerMessage::cell_spawn(entity, genome, transform, *velocity, *force, *ang_velocity, *ang_force, *energy)
         );
     }
     for cell_entity in despawn_event_reader.iter() {
@@ -150,9 +159,14 @@ fn food_spawn_handler(
 fn update_cells(
     server: Res<Server>,
     mut tick: ResMut<TickCounter>,
+    simulation_tick: Res<SimulationTick>,
     cell_query: Query<(Entity, &Transform, &Velocity, &Force, &AngularVelocity, &AngularForce, &Energy)>,
     ) {
     let endpoint = server.endpoint();
+    let _ = endpoint.broadcast_message_on::<ServerMessage>(
+        ChannelId::Unreliable,
+        ServerMessage::Clock(Tick::new(**simulation_tick))
+    );
     for (entity, transform, velocity, force, ang_velocity, ang_force, energy) in cell_query.iter() {
         let _ = endpoint.broadcast_message_on::<ServerMessage>(
             ChannelId::Unreliable, 
@@ -2,7 +2,9 @@ use bevy::prelude::{Deref, DerefMut, Entity, Transform, Vec2};
 use serde::{Serialize, Deserialize};
 
 use crate::game_logic::{
-    cell::{Energy, FlagellaParams, EyeParams}, 
+    config::{WorldConfig, LightConfig},
+    obstacle::ObstacleShape,
+    cell::{Energy, EyeGene, Genome}, 
     physics::{Force, AngularVelocity, AngularForce, Velocity}, 
     math::quat_to_direction
 };
@@ -29,7 +31,14 @@ pub enum ServerMessage {
     CellSpawn(EntityId, CellParams, CellState),
     CellDespawn(EntityId),
     FoodSpawn(EntityId, Vec2),
-    FoodDespawn(EntityId)
+    FoodDespawn(EntityId),
+    /// Sent first to every new client, which has to wrap and confine cells the same way.
+    World(WorldConfig),
+    /// Sent with the world, so the client can show the light cells are in.
+    Light(LightConfig),
+    Obstacles(Vec<ObstacleShape>),
+    /// The server's simulation tick, sent on connection and with every round of updates.
+    Clock(Tick),
 }
 impl ServerMessage {
     pub fn cell_update(tick: u64, 
@@ -47,8 +56,7 @@ impl ServerMessage {
         )
     }
     pub fn cell_spawn(entity: Entity, 
-        flagella_params: &FlagellaParams,
-        eye_params: &EyeParams,
+        genome: &Genome,
         transform: &Transform, 
         velocity: Velocity, 
         force: Force, 
@@ -57,7 +65,7 @@ impl ServerMessage {
         energy: Energy) -> Self {
         Self::CellSpawn(
             EntityId::new(entity),
-            CellParams::new(flagella_params, eye_params),
+            CellParams::new(genome),
             CellState::new(transform, velocity, force, ang_velocity, ang_force, energy),
         )
     }
@@ -110,13 +118,16 @@ impl CellState {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CellParams {
     pub flagella_params: Vec<(f32, f32)>,
-    pub eye_params: Vec<f32>,
+    pub eye_params: Vec<EyeGene>,
+    pub chloroplasts: u8,
 }
 impl CellParams {
-    pub fn new(flagella_params: &FlagellaParams, eye_params: &EyeParams) -> Self {
+    /// The parts of the genome the client needs to build the body.
+    pub fn new(genome: &Genome) -> Self {
         Self {
-            flagella_params: (**flagella_params).clone(),
-            eye_params: (**eye_params).clone(),
+            flagella_params: genome.flagella.clone(),
+            eye_params: genome.eyes.clone(),
+            chloroplasts: genome.chloroplasts,
         }
     }
 }
\ No newline at end of file
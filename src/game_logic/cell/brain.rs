@@ -0,0 +1,153 @@
+use ndarray::{s, Array1};
+
+use crate::game_logic::config::{BrainModel, PlasticityRule};
+use crate::game_logic::math::*;
+use super::Connection;
+
+/// The neurons of a cell, the first `inputs` neurons are set from outside.
+pub struct Network<'a> {
+    /// Only the enabled connections.
+    pub connections: &'a [Connection],
+    pub biases: &'a Array1<f32>,
+    pub time_constants: &'a Array1<f32>,
+    pub inputs: usize,
+}
+impl Network<'_> {
+    /// `state·W + b`, summed over the connections only.
+    pub fn propagate(&self, state: &Array1<f32>) -> Array1<f32> {
+        let mut next = self.biases.clone();
+        for connection in self.connections {
+            next[connection.to] += state[connection.from] * connection.weight;
+        }
+        next
+    }
+}
+
+/// Brains update every time the cell's thinking timer finishes.
+pub trait Brain: Sync {
+    /// Updates the neuron state, `dt` seconds after the last update.
+    fn think(&self, network: &Network, state: &mut Array1<f32>, dt: f32);
+}
+
+/// Every neuron but the inputs jumps to `tanh(state·W + b)` at once.
+pub struct DiscreteBrain;
+impl Brain for DiscreteBrain {
+    fn think(&self, network: &Network, state: &mut Array1<f32>, _dt: f32) {
+        let mut next = network.propagate(state);
+        next.slice_mut(s![..network.inputs]).assign(&state.slice(s![..network.inputs]));
+        *state = next;
+        state.slice_mut(s![network.inputs..]).map_inplace(tanh_inplace);
+    }
+}
+
+/// Continuous-time recurrent network, integrated with a step of the time since the last update.
+/// Each neuron relaxes towards `tanh(state·W + b)` at the speed of its own time
+/// constant, a time constant equal to the step makes it behave like [`DiscreteBrain`].
+pub struct Ctrnn;
+impl Brain for Ctrnn {
+    fn think(&self, network: &Network, state: &mut Array1<f32>, dt: f32) {
+        let mut target = network.propagate(state);
+        target.map_inplace(tanh_inplace);
+        for i in network.inputs..state.len() {
+            let rate = (dt / network.time_constants[i]).min(1.);
+            state[i] += rate * (target[i] - state[i]);
+        }
+    }
+}
+
+impl BrainModel {
+    pub fn brain(&self) -> &'static dyn Brain {
+        match self {
+            Self::Discrete => &DiscreteBrain,
+            Self::Ctrnn => &Ctrnn,
+        }
+    }
+}
+
+impl PlasticityRule {
+    /// Moves the weight of every connection by its plasticity per second times the
+    /// activities of its ends, `before` and `after` an update `dt` seconds long.
+    /// The modulated rule also scales the change by `modulation`. Learning never pushes
+    /// a weight beyond `max_weight`, nor further beyond it if it was born there.
+    pub fn learn(
+        &self,
+        connections: &mut [Connection],
+        before: &Array1<f32>,
+        after: &Array1<f32>,
+        modulation: f32,
+        dt: f32,
+        max_weight: f32,
+    ) {
+        let factor = match self {
+            Self::Off => return,
+            Self::Hebbian => dt,
+            Self::Modulated => dt * modulation,
+        };
+        for connection in connections.iter_mut().filter(|c| c.plasticity != 0.) {
+            let change = connection.plasticity * factor * before[connection.from] * after[connection.to];
+            let limit = max_weight.max(connection.weight.abs());
+            connection.weight = (connection.weight + change).clamp(-limit, limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_ctrnn() {
+        let connections = [(0, 1, 1.), (0, 2, 0.5), (2, 2, -1.)]
+            .map(|(from, to, weight)| Connection::new(0, from, to, weight));
+        let biases = array![0., 0.2, 0.1];
+        let network = Network {
+            connections: &connections,
+            biases: &biases,
+            time_constants: &array![1., 0.1, 1.],
+            inputs: 1,
+        };
+        let input = array![1., 0., 0.];
+        assert_eq!(network.propagate(&array![1., 0., 2.]), array![0., 1.2, -1.4]);
+
+        let mut discrete = input.clone();
+        DiscreteBrain.think(&network, &mut discrete, 0.1);
+        let mut continuous = input.clone();
+        Ctrnn.think(&network, &mut continuous, 0.1);
+        // the inputs are left alone, a neuron as fast as the step jumps right away
+        assert_eq!(discrete[0], 1.);
+        assert_eq!(continuous[0], 1.);
+        assert!((continuous[1] - discrete[1]).abs() < 1e-6);
+        // while a slow one only moves a tenth of the way
+        assert!((continuous[2] - discrete[2] / 10.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_plasticity() {
+        let mut connections = [(0, 1, 0.5), (1, 1, 0.)]
+            .map(|(from, to, weight)| Connection { plasticity: 2., ..Connection::new(0, from, to, weight) });
+        let before = array![1., -0.5];
+        let after = array![1., 0.5];
+
+        let mut learned = connections;
+        PlasticityRule::Off.learn(&mut learned, &before, &after, 1., 0.1, 1.);
+        assert_eq!(learned, connections);
+        PlasticityRule::Hebbian.learn(&mut learned, &before, &after, 0., 0.1, 1.);
+        assert!((learned[0].weight - 0.6).abs() < 1e-6);
+        assert!((learned[1].weight + 0.05).abs() < 1e-6);
+        // rewarded pairs strengthen up to the limit, punished ones weaken
+        PlasticityRule::Modulated.learn(&mut connections, &before, &after, 10., 0.1, 0.8);
+        assert_eq!(connections[0].weight, 0.8);
+        PlasticityRule::Modulated.learn(&mut connections, &before, &after, -1., 0.1, 0.8);
+        assert!((connections[0].weight - 0.7).abs() < 1e-6);
+
+        // fixed connections keep their weight even beyond the limit, plastic ones only move back
+        let mut strong = [0., 2.].map(|plasticity| Connection { plasticity, ..Connection::new(0, 0, 1, 3.) });
+        PlasticityRule::Hebbian.learn(&mut strong, &before, &after, 0., 0.1, 1.);
+        assert_eq!(strong[0].weight, 3.);
+        assert_eq!(strong[1].weight, 3.);
+        PlasticityRule::Hebbian.learn(&mut strong, &before, &array![1., -0.5], 0., 0.1, 1.);
+        assert_eq!(strong[0].weight, 3.);
+        assert!((strong[1].weight - 2.9).abs() < 1e-6);
+    }
+}
@@ -1,8 +1,10 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::Collider;
-use ndarray::{Array2, Array1};
+use ndarray::Array1;
+use serde::{Serialize, Deserialize};
 
 use crate::game_logic::physics::PhysicsBundle;
+use super::{Genome, Connection, FIXED_DELTA, cell_radius};
 
 #[derive(Bundle)]
 pub struct CellBundle {
@@ -12,15 +14,13 @@ pub struct CellBundle {
     pub collider: CellCollider,
     pub sprites: CellSprites,
     pub energy: Energy,
-    pub split_energy: SplitEnergy,
     pub radius: Radius,
     pub dead: Dead,
-    pub chloroplasts: Chloroplasts,
-    pub weights: NeuronWeights,
-    pub biases: NeuronBiases,
+    pub connections: NeuronConnections,
     pub state: NeuronState,
-    pub flagella_params: FlagellaParams,
-    pub eye_params: EyeParams,
+    pub previous_energy: PreviousEnergy,
+    /// Every heritable trait, the other components only hold what changes during the cell's life.
+    pub genome: Genome,
     #[bundle()]
     pub physics_bundle: PhysicsBundle,
     #[bundle()]
@@ -33,13 +33,8 @@ impl CellBundle {
         eyes: Vec<Entity>,
         collider: Entity,
         sprites: Vec<Entity>,
-        flagella_params: Vec<(f32, f32)>,
-        eye_params: Vec<f32>,
+        genome: Genome,
         energy: f32,
-        split_energy: f32,
-        chloroplasts: u8,        
-        weights: Array2<f32>,
-        biases: Array1<f32>,
         state: Array1<f32>,
         position: Vec3,
         rotation: Quat,
@@ -51,21 +46,21 @@ impl CellBundle {
             collider: CellCollider(collider),
             sprites: CellSprites(sprites),
             energy: Energy(energy),
-            split_energy: SplitEnergy(split_energy),
-            radius: Radius(5. * energy.sqrt()),
+            radius: Radius(cell_radius(energy)),
             dead: Dead(false),
-            chloroplasts: Chloroplasts(chloroplasts),
-            weights: NeuronWeights(weights),
-            biases: NeuronBiases(biases),
+            connections: NeuronConnections(genome.connections.iter().filter(|c| c.enabled).copied().collect()),
             state: NeuronState(state),
-            flagella_params: FlagellaParams(flagella_params),
-            eye_params: EyeParams(eye_params),
+            previous_energy: PreviousEnergy(energy),
+            thinking_timer: ThinkingTimer {
+                timer: Timer::from_seconds(genome.think_interval.max(FIXED_DELTA), TimerMode::Repeating),
+                since_update: 0.,
+            },
+            genome,
             physics_bundle: PhysicsBundle::new(),
             spatial_bundle: SpatialBundle::from_transform(
                 Transform::from_translation(position)
                     .with_rotation(rotation)
             ),
-            thinking_timer: ThinkingTimer(Timer::from_seconds(1./20., TimerMode::Repeating)),
         }
     }
 }
@@ -91,6 +86,7 @@ pub struct EyeBundle {
     eye: Eye,
     view_params: ViewParams,
     activation: Activation,
+    vision: Vision,
     #[bundle()]
     spatial_bundle: SpatialBundle,
     collider: Collider,
@@ -108,6 +104,7 @@ impl EyeBundle {
                 range: range, 
             },
             activation: Activation(0.),
+            vision: Vision::default(),
             spatial_bundle: SpatialBundle::from_transform(
                 Transform::from_rotation(Quat::from_rotation_z(position))
                     .with_translation(Vec3::new(horiz, vert, 2.))
@@ -120,11 +117,12 @@ impl EyeBundle {
 #[derive(Bundle, Default)]
 pub struct FoodBundle {
     food: Food,
+    nutrient: Nutrient,
     dead: Dead,
 }
 impl FoodBundle {
-    pub fn new() -> Self {
-        FoodBundle { food: Food{}, dead: Dead(false) }
+    pub fn new(energy: f32) -> Self {
+        FoodBundle { food: Food{}, nutrient: Nutrient(energy), dead: Dead(false) }
     }
 }
 
@@ -147,6 +145,14 @@ pub struct ViewParams {
 #[derive(Component, Default, Clone, Copy)]
 pub struct Food;
 
+/// Food left behind by a starved cell, its [`Nutrient`] slowly decays.
+#[derive(Component, Default, Clone, Copy)]
+pub struct Corpse;
+
+/// Energy gained by the cell that eats this food.
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
+pub struct Nutrient(pub f32);
+
 #[derive(Component, Deref, DerefMut, Default)]
 pub struct CellFlagella(pub Vec<Entity>);
 
@@ -162,41 +168,80 @@ pub struct CellColliderTag;
 #[derive(Component, Deref, DerefMut)]
 pub struct CellSprites(pub Vec<Entity>);
 
-#[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct Energy(pub f32);
 
+/// Energy at the last brain update, its change modulates plasticity.
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
-pub struct SplitEnergy(pub f32);
+pub struct PreviousEnergy(pub f32);
 
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
 pub struct Dead(pub bool);
 
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct NeuronWeights(pub Array2<f32>);
-
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct NeuronBiases(pub Array1<f32>);
+/// The enabled connections of the genome, whose weights change as the cell learns.
+#[derive(Component, Deref, DerefMut, Default, Clone, Serialize, Deserialize)]
+pub struct NeuronConnections(pub Vec<Connection>);
 
-#[derive(Component, Deref, DerefMut, Default)]
+#[derive(Component, Deref, DerefMut, Default, Clone, Serialize, Deserialize)]
 pub struct NeuronState(pub Array1<f32>);
 
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct Chloroplasts(pub u8);
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
+pub struct Activation(pub f32);
 
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct FlagellaParams(pub Vec<(f32,f32)>);
+/// Number of [`InternalSensor`]s, they feed the first neurons of every brain.
+pub const SENSORS: usize = 7;
+
+/// What a cell feels of its own state, each sensor feeds its own input neuron.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InternalSensor {
+    /// Energy relative to [`Genome::split_energy`], division happens at 1.
+    Energy,
+    ForwardVelocity,
+    LateralVelocity,
+    AngularVelocity,
+    /// Time since birth, squashed into `[0, 1)`.
+    Age,
+    /// A sine wave in phase with the cell's birth.
+    Clock,
+    /// Light where the cell is, squashed into `[0, 1)`.
+    Light,
+}
 
-#[derive(Component, Deref, DerefMut, Default)]
-pub struct EyeParams(pub Vec<f32>);
+/// Number of [`EyeChannel`]s, each feeds its own input neuron.
+pub const EYE_CHANNELS: usize = 4;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EyeChannel {
+    Cell,
+    Food,
+    Obstacle,
+    /// Size of the closest cell in view relative to the own one, 0.5 when they are equal.
+    CellSize,
+}
+
+/// What an eye sees on every [`EyeChannel`], the closeness channels go from 0 (nothing)
+/// to 1 (touching). The eye's [`Activation`] is the strongest of them.
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
-pub struct Activation(pub f32);
+pub struct Vision(pub [f32; EYE_CHANNELS]);
+impl Vision {
+    pub fn see(&mut self, channel: EyeChannel, activation: f32) {
+        let value = &mut self.0[channel as usize];
+        *value = value.max(activation);
+    }
+}
 
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
 pub struct Angle(pub f32);
 
+/// Paces the brain updates of a cell.
 #[derive(Component, Deref, DerefMut, Default)]
-pub struct ThinkingTimer(pub Timer);
+pub struct ThinkingTimer {
+    #[deref]
+    pub timer: Timer,
+    /// Simulated seconds since the last update. Updates only happen on ticks, so this
+    /// differs from the timer's duration when that is not a whole number of ticks.
+    pub since_update: f32,
+}
 
 #[derive(Component, Deref, DerefMut, Default)]
 pub struct Radius(pub f32);
\ No newline at end of file
@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::game_logic::config::*;
+use crate::game_logic::math::*;
+use crate::game_logic::sprites::*;
+use super::*;
+
+/// Index of the [`FoodSource`] an item was spawned by.
+#[derive(Component, Deref, DerefMut, Clone, Copy)]
+pub struct FoodOrigin(pub usize);
+
+/// Food owed by every source, spawning happens once a whole item has accumulated.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct FoodBacklog(pub Vec<f32>);
+
+pub fn food_spawning(
+    mut commands: Commands,
+    mut food_spawn_event_writer: EventWriter<FoodSpawnEvent>,
+    mut backlog: ResMut<FoodBacklog>,
+    food_query: Query<(&FoodOrigin, &Dead), With<Food>>,
+    food_sprite: Option<Res<FoodSprite>>,
+    light_sprite: Option<Res<LightSprite>>,
+    mut rng: ResMut<SimRng>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
+) {
+    let time = **tick as f32 * FIXED_DELTA;
+    backlog.resize(config.food.len(), 0.);
+
+    let mut counts = vec![0; config.food.len()];
+    for (origin, dead) in food_query.iter() {
+        if !**dead && **origin < counts.len() {
+            counts[**origin] += 1;
+        }
+    }
+
+    for (i, source) in config.food.iter().enumerate() {
+        if !source.shape.active(time) {
+            continue;
+        }
+        backlog[i] += source.rate * FIXED_DELTA;
+        while backlog[i] >= 1. {
+            backlog[i] -= 1.;
+            // a full source does not save up food for later
+            if counts[i] >= source.cap {
+                continue;
+            }
+            counts[i] += 1;
+            let food = spawn_food(
+                &mut commands,
+                &mut food_spawn_event_writer,
+                config.world.shape.contain(source.shape.sample(time, &mut **rng)).extend(0.),
+                source.energy,
+                food_sprite.as_deref(),
+                light_sprite.as_deref(),
+            );
+            commands.entity(food).insert(FoodOrigin(i));
+        }
+    }
+}
+
+pub fn cell_food_intersection(
+    mut despawn_queue: ResMut<DelayedDespawnQueue>,
+    mut cell_query: Query<(&mut Energy, &Radius, &CellCollider, &Dead), With<Cell>>,
+    collider_query: Query<(&Collider, &GlobalTransform)>,
+    mut food_query: Query<(&mut Dead, &Nutrient), (With<Food>, Without<Cell>)>,
+    rapier_context: Res<RapierContext>,
+    mut food_despawn_event_writer: EventWriter<FoodDespawnEvent>,
+    config: Res<SimulationConfig>,
+) {
+    for (mut energy, radius, collider_entity, dead) in cell_query.iter_mut() {
+        if **dead {
+            continue;
+        }
+        if let Ok((collider, transform)) = collider_query.get(**collider_entity) {
+            let direction = quat_to_direction(transform.to_scale_rotation_translation().1);
+            let angle = (-direction.x).atan2(direction.y);
+            for shift in config.world.shape.shifts(transform.translation().truncate(), **radius) {
+                rapier_context.intersections_with_shape(
+                    transform.translation().truncate() + shift,
+                    angle,
+                    collider,
+                    QueryFilter::default(),
+                    |x| {
+                        if let Ok((mut eaten, nutrient)) = food_query.get_mut(x) {
+                            if !**eaten {
+                                **eaten = true;
+                                despawn_food(&mut despawn_queue, &mut food_despawn_event_writer, x);
+                                **energy += **nutrient;
+                            }
+                        }
+                        true
+                    }
+                )
+            }
+        }
+    }
+}
+
+pub fn corpse_decay(
+    mut despawn_queue: ResMut<DelayedDespawnQueue>,
+    mut food_despawn_event_writer: EventWriter<FoodDespawnEvent>,
+    mut corpse_query: Query<(Entity, &mut Nutrient, &mut Dead), With<Corpse>>,
+    config: Res<SimulationConfig>,
+) {
+    let decay = 0.5_f32.powf(FIXED_DELTA / config.corpse.half_life);
+    for (entity, mut nutrient, mut dead) in corpse_query.iter_mut() {
+        if **dead {
+            continue;
+        }
+        **nutrient *= decay;
+        if **nutrient < config.corpse.min_energy {
+            **dead = true;
+            despawn_food(&mut despawn_queue, &mut food_despawn_event_writer, entity);
+        }
+    }
+}
+
+/// Cells with a mouth drain overlapping smaller cells and swallow much smaller ones whole.
+pub fn cell_predation(
+    mut despawn_queue: ResMut<DelayedDespawnQueue>,
+    mut cell_despawn_event_writer: EventWriter<CellDespawnEvent>,
+    collider_query: Query<(&Parent, &Collider), With<CellColliderTag>>,
+    mut cell_query: Query<(Entity, &Transform, &Radius, &Genome, &CellCollider, &mut Energy, &mut Dead), With<Cell>>,
+    rapier_context: Res<RapierContext>,
+    mut cell_count: ResMut<CellCount>,
+    config: Res<SimulationConfig>,
+) {
+    let predation = &config.predation;
+
+    // find all attacks first, energy only changes hands once they are known
+    let mut attacks: Vec<(Entity, Entity)> = Vec::new();
+    for (predator, transform, radius, genome, cell_collider, _, dead) in cell_query.iter() {
+        if **dead || genome.mouth <= 0. {
+            continue;
+        }
+        let Ok((_, collider)) = collider_query.get(**cell_collider) else {
+            continue;
+        };
+        for shift in config.world.shape.shifts(transform.translation.truncate(), **radius) {
+            rapier_context.intersections_with_shape(
+                transform.translation.truncate() + shift,
+                0.,
+                collider,
+                QueryFilter::default(),
+                |x| {
+                    if let Ok((parent, _)) = collider_query.get(x) {
+                        if let Ok((prey, _, prey_radius, ..)) = cell_query.get(parent.get()) {
+                            if prey != predator && predation.can_attack(**radius, **prey_radius) {
+                                attacks.push((predator, prey));
+                            }
+                        }
+                    }
+                    true
+                }
+            );
+        }
+    }
+    attacks.sort_unstable();
+    // a prey seen through several images of a tiny world is still attacked only once
+    attacks.dedup();
+
+    for (predator, prey) in attacks {
+        let Ok([
+            (_, _, radius, genome, _, mut energy, dead),
+            (_, _, prey_radius, _, _, mut prey_energy, mut prey_dead),
+        ]) = cell_query.get_many_mut([predator, prey]) else {
+            continue;
+        };
+        if **dead || **prey_dead {
+            continue;
+        }
+
+        let (taken, engulfed) = predation.bite(genome.mouth, **radius, **prey_radius, **prey_energy);
+        **prey_energy -= taken;
+        **energy += taken * predation.efficiency;
+
+        if engulfed || **prey_energy <= 0. {
+            **prey_dead = true;
+            despawn_cell(&mut despawn_queue, &mut cell_despawn_event_writer, prey, cell_count.as_mut());
+        }
+    }
+}
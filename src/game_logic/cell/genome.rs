@@ -0,0 +1,804 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use bevy::prelude::*;
+use ndarray::{Array1, Axis};
+use ndarray_rand::RandomExt;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use rand_distr::{Distribution, Normal, Uniform};
+use serde::{Serialize, Deserialize};
+
+use crate::game_logic::config::MutationConfig;
+use super::{EYE_CHANNELS, SENSORS, FIXED_DELTA, Innovations};
+
+/// Shortest and longest neuron time constant, in seconds.
+pub const TIME_CONSTANT_LIMITS: (f32, f32) = (FIXED_DELTA, 10.);
+/// Smallest and largest share of the energy the smaller daughter gets. Much smaller
+/// daughters would start below a quarter of their split energy and starve at once.
+pub const SPLIT_RATIO_LIMITS: (f32, f32) = (0.3, 0.5);
+
+/// Shortest and longest time between two brain updates, in seconds.
+pub const THINK_INTERVAL_LIMITS: (f32, f32) = (FIXED_DELTA, 1.);
+pub const DEFAULT_THINK_INTERVAL: f32 = 1. / 20.;
+/// Time constant of new neurons, as fast as a brain thinking at the default interval.
+pub const DEFAULT_TIME_CONSTANT: f32 = DEFAULT_THINK_INTERVAL;
+
+/// Narrowest and widest field of view of an eye, in radians.
+pub const EYE_FOV_LIMITS: (f32, f32) = (PI / 180., PI * 17. / 18.);
+/// Shortest and longest range of an eye.
+pub const EYE_RANGE_LIMITS: (f32, f32) = (10., 3000.);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EyeGene {
+    /// Angle around the cell.
+    pub position: f32,
+    pub fov: f32,
+    pub range: f32,
+}
+impl EyeGene {
+    pub fn new(position: f32) -> Self {
+        Self { position, ..default() }
+    }
+
+    /// Area of the sector the eye senses.
+    pub fn area(&self) -> f32 {
+        self.fov / 2. * self.range * self.range
+    }
+}
+impl Default for EyeGene {
+    fn default() -> Self {
+        Self { position: 0., fov: f32::to_radians(30.), range: 1000. }
+    }
+}
+
+/// A weighted link between two neurons. The innovation number stays with a connection
+/// through mutation and crossover, two genomes sharing one both inherited it from the
+/// same structural mutation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Connection {
+    pub innovation: u64,
+    pub from: usize,
+    pub to: usize,
+    pub weight: f32,
+    /// Learning rate of the weight during the cell's life, see [`crate::game_logic::config::PlasticityRule`].
+    pub plasticity: f32,
+    /// Connections split by a new neuron are kept disabled, so they still line up in crossover.
+    pub enabled: bool,
+}
+impl Connection {
+    pub fn new(innovation: u64, from: usize, to: usize, weight: f32) -> Self {
+        Self { innovation, from, to, weight, plasticity: 0., enabled: true }
+    }
+}
+
+/// All heritable data of a cell. Daughters are built from a mutated copy of it.
+///
+/// Neurons are laid out as `[sensor inputs | eye inputs | hidden | divide | flagella outputs]`,
+/// one input per [`super::InternalSensor`], [`EYE_CHANNELS`] consecutive inputs per eye,
+/// a single output asking for division and one output per flagellum. Input neurons are
+/// only ever connected from.
+#[derive(Component, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Genome {
+    pub split_energy: f32,
+    /// Share of the energy the smaller daughter gets.
+    pub split_ratio: f32,
+    pub chloroplasts: u8,
+    /// Strength of the mouth in `[0, 1]`, cells without one cannot prey on others.
+    pub mouth: f32,
+    /// Seconds between two brain updates.
+    pub think_interval: f32,
+    /// `(position, angle)` of every flagellum, position is the angle around the cell.
+    pub flagella: Vec<(f32, f32)>,
+    pub eyes: Vec<EyeGene>,
+    /// Sorted by innovation number.
+    pub connections: Vec<Connection>,
+    /// Innovation number of the connection every hidden neuron split, in the order of the
+    /// hidden neurons. Neurons that split the same connection line up in crossover.
+    pub hidden: Vec<u64>,
+    pub biases: Array1<f32>,
+    /// Seconds every neuron takes to follow its input, only used by the continuous brain.
+    pub time_constants: Array1<f32>,
+}
+
+impl Genome {
+    /// A genome with randomly placed organs and a random, fully connected brain.
+    pub fn random(
+        rng: &mut impl Rng,
+        innovations: &mut Innovations,
+        split_energy: f32,
+        chloroplasts: u8,
+        flagella: usize,
+        eyes: usize,
+        hidden: usize,
+    ) -> Self {
+        let around = Uniform::new(0., 2. * PI);
+        let angle = Uniform::new_inclusive(-PI / 2., PI / 2.);
+        let inputs = SENSORS + eyes * EYE_CHANNELS;
+        let neurons = inputs + hidden + 1 + flagella;
+        let normal = Normal::new(0., 0.5).unwrap();
+        let connections = (inputs..neurons)
+            .flat_map(|to| (0..neurons).map(move |from| (from, to)))
+            .map(|(from, to)| Connection::new(innovations.fresh(), from, to, normal.sample(rng)))
+            .collect();
+
+        Self {
+            split_energy,
+            split_ratio: 0.5,
+            chloroplasts,
+            mouth: 0.,
+            think_interval: DEFAULT_THINK_INTERVAL,
+            flagella: (0..flagella).map(|_| (around.sample(rng), angle.sample(rng))).collect(),
+            eyes: (0..eyes).map(|_| EyeGene::new(around.sample(rng))).collect(),
+            connections,
+            hidden: (0..hidden).map(|_| innovations.fresh()).collect(),
+            biases: Array1::random_using(neurons, normal, rng),
+            time_constants: Array1::from_elem(neurons, DEFAULT_TIME_CONSTANT),
+        }
+    }
+
+    pub fn mutate(&self, rng: &mut impl Rng, config: &MutationConfig, innovations: &mut Innovations) -> Self {
+        let normal = Normal::new(0., config.rate).unwrap();
+        let weight_normal = Normal::new(0., config.weight_rate).unwrap();
+        let split_normal = Normal::new(0., config.split_energy_rate).unwrap();
+        let fov_normal = Normal::new(0., config.eye_fov_rate).unwrap();
+        let range_normal = Normal::new(0., config.eye_range_rate).unwrap();
+        let time_constant_normal = Normal::new(0., config.time_constant_rate).unwrap();
+        let think_normal = Normal::new(0., config.think_interval_rate).unwrap();
+        let plasticity_normal = Normal::new(0., config.plasticity_rate).unwrap();
+        let ratio_normal = Normal::new(0., config.split_ratio_rate).unwrap();
+        let mouth_normal = Normal::new(0., config.mouth_rate).unwrap();
+
+        let mut genome = Self {
+            split_energy: (self.split_energy + split_normal.sample(rng)).max(config.min_split_energy),
+            split_ratio: (self.split_ratio + ratio_normal.sample(rng)).clamp(SPLIT_RATIO_LIMITS.0, SPLIT_RATIO_LIMITS.1),
+            chloroplasts: match rng.gen_bool(config.chloroplast_rate) {
+                true if rng.gen_bool(0.5) => self.chloroplasts.saturating_add(1),
+                true => self.chloroplasts.saturating_sub(1),
+                false => self.chloroplasts,
+            },
+            mouth: (self.mouth + mouth_normal.sample(rng)).clamp(0., 1.),
+            think_interval: (self.think_interval * think_normal.sample(rng).exp())
+                .clamp(THINK_INTERVAL_LIMITS.0, THINK_INTERVAL_LIMITS.1),
+            flagella: self.flagella.iter()
+                .map(|(pos, ang)| (pos + normal.sample(rng), (ang + normal.sample(rng)).clamp(-PI/2., PI/2.)))
+                .collect(),
+            eyes: self.eyes.iter()
+                .map(|eye| EyeGene {
+                    position: eye.position + normal.sample(rng),
+                    fov: (eye.fov + fov_normal.sample(rng)).clamp(EYE_FOV_LIMITS.0, EYE_FOV_LIMITS.1),
+                    range: (eye.range * range_normal.sample(rng).exp()).clamp(EYE_RANGE_LIMITS.0, EYE_RANGE_LIMITS.1),
+                })
+                .collect(),
+            connections: self.connections.iter()
+                .map(|c| Connection {
+                    weight: c.weight + weight_normal.sample(rng),
+                    plasticity: c.plasticity + plasticity_normal.sample(rng),
+                    ..*c
+                })
+                .collect(),
+            hidden: self.hidden.clone(),
+            biases: self.biases.map(|x| x + weight_normal.sample(rng)),
+            time_constants: self.time_constants
+                .map(|x| (x * time_constant_normal.sample(rng).exp()).clamp(TIME_CONSTANT_LIMITS.0, TIME_CONSTANT_LIMITS.1)),
+        };
+        genome.mutate_structure(rng, config, innovations);
+        genome
+    }
+
+    /// Mixes this genome with a mate's. The layout (number of organs, neurons and
+    /// connections) is kept, every organ and neuron present in both genomes is taken from
+    /// either parent at random, and so is the weight of every connection with an
+    /// innovation number the mate also has. Hidden neurons are present in both when they
+    /// split the same connection.
+    pub fn crossover(&self, mate: &Genome, rng: &mut impl Rng) -> Self {
+        let mut genome = self.clone();
+        if rng.gen_bool(0.5) {
+            genome.split_energy = mate.split_energy;
+        }
+        if rng.gen_bool(0.5) {
+            genome.split_ratio = mate.split_ratio;
+        }
+        if rng.gen_bool(0.5) {
+            genome.chloroplasts = mate.chloroplasts;
+        }
+        if rng.gen_bool(0.5) {
+            genome.mouth = mate.mouth;
+        }
+        if rng.gen_bool(0.5) {
+            genome.think_interval = mate.think_interval;
+        }
+        for (organ, mate_organ) in genome.flagella.iter_mut().zip(mate.flagella.iter()) {
+            if rng.gen_bool(0.5) {
+                *organ = *mate_organ;
+            }
+        }
+        for (organ, mate_organ) in genome.eyes.iter_mut().zip(mate.eyes.iter()) {
+            if rng.gen_bool(0.5) {
+                *organ = *mate_organ;
+            }
+        }
+
+        for (i, mate_i) in self.aligned_neurons(mate) {
+            if rng.gen_bool(0.5) {
+                genome.biases[i] = mate.biases[mate_i];
+                genome.time_constants[i] = mate.time_constants[mate_i];
+            }
+        }
+        let mate_connections: HashMap<u64, &Connection> = mate.connections.iter().map(|c| (c.innovation, c)).collect();
+        for connection in genome.connections.iter_mut() {
+            if let Some(mate_connection) = mate_connections.get(&connection.innovation) {
+                if rng.gen_bool(0.5) {
+                    connection.weight = mate_connection.weight;
+                    connection.plasticity = mate_connection.plasticity;
+                }
+            }
+        }
+        genome
+    }
+
+    fn mutate_structure(&mut self, rng: &mut impl Rng, config: &MutationConfig, innovations: &mut Innovations) {
+        let chances = &config.structure;
+        let around = Uniform::new(0., 2. * PI);
+        let angle = Uniform::new_inclusive(-PI / 2., PI / 2.);
+        let weight_normal = Normal::new(0., config.weight_rate).unwrap();
+
+        if !self.flagella.is_empty() && rng.gen_bool(chances.remove_flagellum) {
+            let i = rng.gen_range(0..self.flagella.len());
+            self.remove_neuron(self.flagella_neurons().start + i);
+            self.flagella.remove(i);
+        }
+        if rng.gen_bool(chances.add_flagellum) {
+            let i = rng.gen_range(0..=self.flagella.len());
+            let index = self.flagella_neurons().start + i;
+            self.insert_neuron(index);
+            self.flagella.insert(i, (around.sample(rng), angle.sample(rng)));
+            let from = rng.gen_range(0..self.neuron_count());
+            self.push_connection(Connection::new(innovations.connection(from, index), from, index, weight_normal.sample(rng)), innovations);
+        }
+        if !self.eyes.is_empty() && rng.gen_bool(chances.remove_eye) {
+            let i = rng.gen_range(0..self.eyes.len());
+            for _ in 0..EYE_CHANNELS {
+                self.remove_neuron(self.eye_neurons().start + i * EYE_CHANNELS);
+            }
+            self.eyes.remove(i);
+        }
+        if rng.gen_bool(chances.add_eye) {
+            let i = rng.gen_range(0..=self.eyes.len());
+            for _ in 0..EYE_CHANNELS {
+                self.insert_neuron(self.eye_neurons().start + i * EYE_CHANNELS);
+            }
+            self.eyes.insert(i, EyeGene::new(around.sample(rng)));
+            // every channel of the new eye feeds a random neuron that is not an input
+            for channel in 0..EYE_CHANNELS {
+                let from = self.eye_neurons().start + i * EYE_CHANNELS + channel;
+                let to = rng.gen_range(self.eye_neurons().end..self.neuron_count());
+                self.push_connection(Connection::new(innovations.connection(from, to), from, to, weight_normal.sample(rng)), innovations);
+            }
+        }
+        let hidden = self.hidden_neurons();
+        if !hidden.is_empty() && rng.gen_bool(chances.remove_neuron) {
+            let index = rng.gen_range(hidden.clone());
+            self.remove_neuron(index);
+            self.hidden.remove(index - hidden.start);
+        }
+        if rng.gen_bool(chances.add_neuron) {
+            self.split_connection(rng, innovations);
+        }
+        if rng.gen_bool(chances.add_connection) {
+            self.add_connection(rng, innovations, weight_normal);
+        }
+    }
+
+    /// Puts a new hidden neuron in the middle of a random enabled connection. The old
+    /// connection is disabled, the incoming one gets a weight of 1 and the outgoing one
+    /// the old weight, so the behaviour barely changes.
+    fn split_connection(&mut self, rng: &mut impl Rng, innovations: &mut Innovations) {
+        let index = self.hidden_neurons().end;
+        let enabled: Vec<usize> = (0..self.connections.len()).filter(|i| self.connections[*i].enabled).collect();
+        self.insert_neuron(index);
+        let Some(split) = enabled.choose(rng).copied() else {
+            self.hidden.push(innovations.fresh());
+            return;
+        };
+        let old = &mut self.connections[split];
+        old.enabled = false;
+        let (from, to, weight, innovation) = (old.from, old.to, old.weight, old.innovation);
+        self.hidden.push(innovation);
+        let (incoming, outgoing) = innovations.split(innovation);
+        self.push_connection(Connection::new(incoming, from, index, 1.), innovations);
+        self.push_connection(Connection::new(outgoing, index, to, weight), innovations);
+    }
+
+    /// Connects two random neurons that are not connected yet.
+    fn add_connection(&mut self, rng: &mut impl Rng, innovations: &mut Innovations, weight_normal: Normal<f32>) {
+        let n = self.neuron_count();
+        let inputs = self.eye_neurons().end;
+        if inputs == n {
+            return;
+        }
+        let from = rng.gen_range(0..n);
+        let to = rng.gen_range(inputs..n);
+        if self.connections.iter().any(|c| c.from == from && c.to == to) {
+            return;
+        }
+        self.push_connection(Connection::new(innovations.connection(from, to), from, to, weight_normal.sample(rng)), innovations);
+    }
+
+    /// Adds a connection, keeping them sorted by innovation number. Innovations handed out
+    /// earlier in the same tick can be older than ones the genome already has, or even
+    /// taken by one of them once neurons have moved.
+    fn push_connection(&mut self, connection: Connection, innovations: &mut Innovations) {
+        match self.connections.binary_search_by_key(&connection.innovation, |c| c.innovation) {
+            Ok(_) => self.connections.push(Connection { innovation: innovations.fresh(), ..connection }),
+            Err(index) => self.connections.insert(index, connection),
+        }
+    }
+
+    /// Inserts an unconnected neuron at rest.
+    fn insert_neuron(&mut self, index: usize) {
+        for connection in self.connections.iter_mut() {
+            if connection.from >= index {
+                connection.from += 1;
+            }
+            if connection.to >= index {
+                connection.to += 1;
+            }
+        }
+        let mut biases = std::mem::take(&mut self.biases).to_vec();
+        biases.insert(index, 0.);
+        self.biases = Array1::from_vec(biases);
+        let mut time_constants = std::mem::take(&mut self.time_constants).to_vec();
+        time_constants.insert(index, DEFAULT_TIME_CONSTANT);
+        self.time_constants = Array1::from_vec(time_constants);
+    }
+
+    /// Removes a neuron along with all its connections.
+    fn remove_neuron(&mut self, index: usize) {
+        self.connections.retain(|c| c.from != index && c.to != index);
+        for connection in self.connections.iter_mut() {
+            if connection.from > index {
+                connection.from -= 1;
+            }
+            if connection.to > index {
+                connection.to -= 1;
+            }
+        }
+        let keep: Vec<usize> = (0..self.neuron_count()).filter(|i| *i != index).collect();
+        self.biases = self.biases.select(Axis(0), &keep);
+        self.time_constants = self.time_constants.select(Axis(0), &keep);
+    }
+
+    /// Whether a cell with `energy` and neuron `state` splits. When division is
+    /// `voluntary` its divide neuron has to fire as well.
+    pub fn ready_to_split(&self, energy: f32, state: &Array1<f32>, voluntary: bool) -> bool {
+        if energy < self.split_energy {
+            return false;
+        }
+        !voluntary || state.get(self.divide_neuron()).is_some_and(|activation| *activation > 0.)
+    }
+
+    /// Energies of the two daughters of a cell with `energy`, the bigger one first.
+    pub fn split_energies(&self, energy: f32) -> [f32; 2] {
+        [1. - self.split_ratio, self.split_ratio].map(|share| energy * share)
+    }
+
+    pub fn neuron_count(&self) -> usize {
+        self.biases.len()
+    }
+
+    pub fn sensor_neurons(&self) -> Range<usize> {
+        0..SENSORS
+    }
+
+    pub fn eye_neurons(&self) -> Range<usize> {
+        SENSORS..SENSORS + self.eyes.len() * EYE_CHANNELS
+    }
+
+    pub fn hidden_neurons(&self) -> Range<usize> {
+        self.eye_neurons().end..self.divide_neuron()
+    }
+
+    pub fn divide_neuron(&self) -> usize {
+        self.neuron_count() - self.flagella.len() - 1
+    }
+
+    pub fn flagella_neurons(&self) -> Range<usize> {
+        self.neuron_count() - self.flagella.len()..self.neuron_count()
+    }
+
+    /// Pairs of neuron indices `(self, other)` that play the same role in both genomes.
+    /// Organ blocks of the layout are matched from their start, so the n-th eye input of
+    /// one genome corresponds to the n-th eye input of the other. Hidden neurons are
+    /// matched by the connection they split.
+    pub fn aligned_neurons(&self, other: &Genome) -> Vec<(usize, usize)> {
+        let other_hidden: HashMap<u64, usize> = other.hidden.iter()
+            .zip(other.hidden_neurons())
+            .map(|(id, i)| (*id, i))
+            .collect();
+        let hidden = self.hidden.iter()
+            .zip(self.hidden_neurons())
+            .filter_map(|(id, i)| other_hidden.get(id).map(|j| (i, *j)));
+        [
+            (self.sensor_neurons(), other.sensor_neurons()),
+            (self.eye_neurons(), other.eye_neurons()),
+            (self.divide_neuron()..self.divide_neuron() + 1, other.divide_neuron()..other.divide_neuron() + 1),
+            (self.flagella_neurons(), other.flagella_neurons()),
+        ]
+            .into_iter()
+            .flat_map(|(a, b)| a.zip(b))
+            .chain(hidden)
+            .collect()
+    }
+
+    /// This genome with the weights a cell learned during its life, `learned` being the
+    /// enabled connections in the same order.
+    pub fn with_learned(&self, learned: &[Connection]) -> Self {
+        let mut genome = self.clone();
+        let mut learned = learned.iter().peekable();
+        for connection in genome.connections.iter_mut() {
+            if let Some(l) = learned.next_if(|l| l.innovation == connection.innovation) {
+                connection.weight = l.weight;
+            }
+        }
+        genome
+    }
+
+    /// Carries the neuron state of a parent over to this genome, neurons the parent
+    /// does not have start at rest.
+    pub fn inherit_state(&self, parent: &Genome, state: &Array1<f32>) -> Array1<f32> {
+        if state.len() != parent.neuron_count() {
+            return Array1::zeros(self.neuron_count());
+        }
+        let mut inherited = Array1::zeros(self.neuron_count());
+        for (i, j) in self.aligned_neurons(parent) {
+            inherited[i] = state[j];
+        }
+        inherited
+    }
+
+    /// Rough genetic distance, 0 for identical genomes. Organs, neurons and connections
+    /// present in only one of the genomes count by their number, shared ones by their
+    /// difference.
+    pub fn distance(&self, other: &Genome) -> f32 {
+        let split = (self.split_energy - other.split_energy).abs() / self.split_energy.max(other.split_energy).max(f32::EPSILON);
+        let split_ratio = (self.split_ratio - other.split_ratio).abs();
+        let chloroplasts = (self.chloroplasts as f32 - other.chloroplasts as f32).abs();
+        let mouth = (self.mouth - other.mouth).abs();
+        let think_interval = (self.think_interval.ln() - other.think_interval.ln()).abs();
+
+        let flagella_count = self.flagella.len().abs_diff(other.flagella.len()) as f32;
+        let flagella: f32 = self.flagella.iter().zip(other.flagella.iter())
+            .map(|((pos_a, ang_a), (pos_b, ang_b))| (pos_a - pos_b).abs() + (ang_a - ang_b).abs())
+            .sum();
+        let eye_count = self.eyes.len().abs_diff(other.eyes.len()) as f32;
+        let eyes: f32 = self.eyes.iter().zip(other.eyes.iter())
+            .map(|(a, b)| (a.position - b.position).abs() + (a.fov - b.fov).abs() + (a.range.ln() - b.range.ln()).abs())
+            .sum();
+
+        let aligned = self.aligned_neurons(other);
+        let neuron_count = (self.neuron_count() + other.neuron_count() - 2 * aligned.len()) as f32;
+        let brain = if aligned.is_empty() {
+            0.
+        } else {
+            let biases: f32 = aligned.iter()
+                .map(|(a, b)| (self.biases[*a] - other.biases[*b]).abs())
+                .sum();
+            let time_constants: f32 = aligned.iter()
+                .map(|(a, b)| (self.time_constants[*a].ln() - other.time_constants[*b].ln()).abs())
+                .sum();
+            (biases + time_constants) / aligned.len() as f32
+        };
+
+        // connections are sorted by innovation, so matching ones are summed in the same order either way
+        let other_connections: HashMap<u64, &Connection> = other.connections.iter().map(|c| (c.innovation, c)).collect();
+        let matching: Vec<f32> = self.connections.iter()
+            .filter_map(|c| other_connections.get(&c.innovation)
+                .map(|o| (c.weight - o.weight).abs() + (c.plasticity - o.plasticity).abs()))
+            .collect();
+        let disjoint = (self.connections.len() + other.connections.len() - 2 * matching.len()) as f32;
+        let connections = disjoint / self.connections.len().max(other.connections.len()).max(1) as f32
+            + matching.iter().sum::<f32>() / matching.len().max(1) as f32;
+
+        split + split_ratio + chloroplasts + mouth + think_interval + flagella_count + flagella + eye_count + eyes + neuron_count + brain + connections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::config::StructuralMutationConfig;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn fixed_structure() -> MutationConfig {
+        MutationConfig {
+            chloroplast_rate: 0.,
+            structure: StructuralMutationConfig {
+                add_flagellum: 0.,
+                remove_flagellum: 0.,
+                add_eye: 0.,
+                remove_eye: 0.,
+                add_neuron: 0.,
+                remove_neuron: 0.,
+                add_connection: 0.,
+            },
+            ..default()
+        }
+    }
+
+    fn assert_consistent(genome: &Genome) {
+        let n = genome.neuron_count();
+        assert_eq!(genome.time_constants.len(), n);
+        assert!(genome.connections.windows(2).all(|w| w[0].innovation < w[1].innovation));
+        for connection in genome.connections.iter() {
+            assert!(connection.from < n);
+            assert!((genome.eye_neurons().end..n).contains(&connection.to));
+        }
+    }
+
+    #[test]
+    fn test_mutate_keeps_shape() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut innovations = Innovations::default();
+        let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 3, 2, 4);
+        let child = genome.mutate(&mut rng, &fixed_structure(), &mut innovations);
+        assert_eq!(child.flagella.len(), 3);
+        assert_eq!(child.eyes.len(), 2);
+        let n = SENSORS + 2 * EYE_CHANNELS + 4 + 1 + 3;
+        assert_eq!(child.neuron_count(), n);
+        assert_eq!(child.connections.len(), (4 + 1 + 3) * n);
+        assert!((SPLIT_RATIO_LIMITS.0..=SPLIT_RATIO_LIMITS.1).contains(&child.split_ratio));
+        assert_consistent(&child);
+        assert!(child.time_constants.iter().all(|t| (TIME_CONSTANT_LIMITS.0..=TIME_CONSTANT_LIMITS.1).contains(t)));
+        assert_eq!(child.chloroplasts, 1);
+        assert!((0. ..=1.).contains(&child.mouth));
+        assert!((THINK_INTERVAL_LIMITS.0..=THINK_INTERVAL_LIMITS.1).contains(&child.think_interval));
+        assert!(child.eyes.iter().all(|eye| (EYE_FOV_LIMITS.0..=EYE_FOV_LIMITS.1).contains(&eye.fov)));
+        assert!(child.eyes.iter().all(|eye| (EYE_RANGE_LIMITS.0..=EYE_RANGE_LIMITS.1).contains(&eye.range)));
+        assert!(child.flagella.iter().all(|(_, ang)| (-PI/2. ..=PI/2.).contains(ang)));
+    }
+
+    #[test]
+    fn test_structural_mutations() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut innovations = Innovations::default();
+        let config = MutationConfig {
+            structure: StructuralMutationConfig {
+                add_flagellum: 0.5,
+                remove_flagellum: 0.5,
+                add_eye: 0.5,
+                remove_eye: 0.5,
+                add_neuron: 0.5,
+                remove_neuron: 0.5,
+                add_connection: 0.5,
+            },
+            ..default()
+        };
+
+        let mut genome = Genome::random(&mut rng, &mut innovations, 100., 1, 0, 0, 0);
+        let mut state = Array1::zeros(0);
+        for _ in 0..200 {
+            let child = genome.mutate(&mut rng, &config, &mut innovations);
+            let n = child.neuron_count();
+            assert_consistent(&child);
+            assert!(SENSORS + child.flagella.len() + child.eyes.len() * EYE_CHANNELS < n);
+            assert_eq!(child.eye_neurons().end, child.hidden_neurons().start);
+            assert_eq!(child.hidden_neurons().end, child.divide_neuron());
+            assert_eq!(child.divide_neuron() + 1, child.flagella_neurons().start);
+
+            state = child.inherit_state(&genome, &state);
+            assert_eq!(state.len(), n);
+            genome = child;
+        }
+        assert!(!genome.connections.is_empty());
+    }
+
+    #[test]
+    fn test_divide_neuron() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut innovations = Innovations::default();
+        let mut genome = Genome::random(&mut rng, &mut innovations, 100., 1, 2, 1, 0);
+        let divide = genome.divide_neuron();
+        assert_eq!(divide, SENSORS + EYE_CHANNELS);
+        let inputs: Vec<u64> = genome.connections.iter().filter(|c| c.to == divide).map(|c| c.innovation).collect();
+
+        // the divide neuron moves up past a new hidden neuron and keeps its connections
+        genome.split_connection(&mut rng, &mut innovations);
+        assert_eq!(genome.divide_neuron(), divide + 1);
+        assert_eq!(genome.flagella_neurons().start, divide + 2);
+        assert!(inputs.iter().all(|i| genome.connections.iter().any(|c| c.innovation == *i && c.to == divide + 1)));
+    }
+
+    #[test]
+    fn test_ready_to_split() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let genome = Genome::random(&mut rng, &mut Innovations::default(), 100., 1, 1, 0, 1);
+        let mut state = Array1::zeros(genome.neuron_count());
+        assert!(!genome.ready_to_split(99., &state, false));
+        assert!(genome.ready_to_split(100., &state, false));
+        // voluntary division also waits for the divide neuron
+        assert!(!genome.ready_to_split(100., &state, true));
+        state[genome.divide_neuron()] = 0.5;
+        assert!(genome.ready_to_split(100., &state, true));
+        assert!(!genome.ready_to_split(99., &state, true));
+        // a state from before a structural change is not read past its end
+        assert!(!genome.ready_to_split(100., &Array1::zeros(SENSORS), true));
+    }
+
+    #[test]
+    fn test_split_energies() {
+        let genome = Genome { split_ratio: 0.3, ..default() };
+        let [bigger, smaller] = genome.split_energies(200.);
+        assert!((bigger - 140.).abs() < 1e-4);
+        assert!((smaller - 60.).abs() < 1e-4);
+        assert_eq!(Genome { split_ratio: 0.5, ..default() }.split_energies(200.), [100., 100.]);
+    }
+
+    #[test]
+    fn test_remove_then_add_neuron() {
+        // the hidden range shrinks with the removal, the insertion must not use the old one
+        let config = MutationConfig {
+            structure: StructuralMutationConfig {
+                add_neuron: 1.,
+                remove_neuron: 1.,
+                ..fixed_structure().structure
+            },
+            ..default()
+        };
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut innovations = Innovations::default();
+            let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 0, 0, 1);
+            let child = genome.mutate(&mut rng, &config, &mut innovations);
+            assert_eq!(child.neuron_count(), genome.neuron_count());
+            assert_eq!(child.hidden_neurons(), genome.hidden_neurons());
+        }
+    }
+
+    #[test]
+    fn test_insert_remove_neuron() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let genome = Genome::random(&mut rng, &mut Innovations::default(), 100., 1, 1, 1, 1);
+        let mut changed = genome.clone();
+        let hidden = genome.hidden_neurons().start;
+        changed.insert_neuron(hidden);
+        for (old, new) in genome.connections.iter().zip(changed.connections.iter()) {
+            assert_eq!(new.from, old.from + (old.from >= hidden) as usize);
+            assert_eq!(new.to, old.to + (old.to >= hidden) as usize);
+        }
+        changed.remove_neuron(hidden);
+        assert_eq!(changed.connections, genome.connections);
+        assert_eq!(changed.biases, genome.biases);
+        assert_eq!(changed.time_constants, genome.time_constants);
+    }
+
+    #[test]
+    fn test_split_connection() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut innovations = Innovations::default();
+        let mut genome = Genome::random(&mut rng, &mut innovations, 100., 1, 1, 0, 0);
+        let count = genome.connections.len();
+        genome.split_connection(&mut rng, &mut innovations);
+
+        assert_eq!(genome.hidden_neurons().len(), 1);
+        assert_eq!(genome.connections.len(), count + 2);
+        assert_consistent(&genome);
+        let split = genome.connections.iter().find(|c| !c.enabled).unwrap();
+        let [incoming, outgoing] = [count, count + 1].map(|i| genome.connections[i]);
+        assert_eq!((incoming.from, incoming.to, incoming.weight), (split.from, SENSORS, 1.));
+        assert_eq!((outgoing.from, outgoing.to, outgoing.weight), (SENSORS, split.to, split.weight));
+    }
+
+    #[test]
+    fn test_shared_innovations() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut innovations = Innovations::default();
+        let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 1, 0, 0);
+        let split = |seed, innovations: &mut Innovations| {
+            let mut genome = genome.clone();
+            genome.split_connection(&mut StdRng::seed_from_u64(seed), innovations);
+            genome
+        };
+        let (a, b) = (split(0, &mut innovations), split(0, &mut innovations));
+        let other = (1..).map(|seed| split(seed, &mut innovations)).find(|g| g.hidden != a.hidden).unwrap();
+        // the same split in one generation gets the same innovations and lines up
+        assert_eq!(a.connections, b.connections);
+        assert!(a.aligned_neurons(&b).contains(&(SENSORS, SENSORS)));
+        // a different split is a different neuron, though it sits at the same index
+        assert!(!a.aligned_neurons(&other).contains(&(SENSORS, SENSORS)));
+        assert_eq!(a.aligned_neurons(&other).len(), a.neuron_count() - 1);
+        assert!(a.distance(&other) > a.distance(&b));
+
+        innovations.forget();
+        let c = split(0, &mut innovations);
+        assert_eq!(c.hidden, a.hidden);
+        assert!(c.connections.last().unwrap().innovation > a.connections.last().unwrap().innovation);
+    }
+
+    #[test]
+    fn test_new_organs_are_wired() {
+        let config = MutationConfig {
+            structure: StructuralMutationConfig { add_flagellum: 1., add_eye: 1., ..fixed_structure().structure },
+            ..fixed_structure()
+        };
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut innovations = Innovations::default();
+        let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 0, 0, 0);
+        let child = genome.mutate(&mut rng, &config, &mut innovations);
+        assert_consistent(&child);
+        assert_eq!(child.connections.len(), genome.connections.len() + 1 + EYE_CHANNELS);
+        let flagellum = child.flagella_neurons().start;
+        assert!(child.connections.iter().any(|c| c.to == flagellum));
+        for eye_input in child.eye_neurons() {
+            assert!(child.connections.iter().any(|c| c.from == eye_input));
+        }
+    }
+
+    #[test]
+    fn test_crossover() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut innovations = Innovations::default();
+        let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 3, 2, 4);
+        let mate = Genome::random(&mut rng, &mut innovations, 200., 3, 1, 3, 0);
+        let relative = genome.mutate(&mut rng, &default(), &mut innovations);
+        let child = genome.crossover(&mate, &mut rng);
+
+        assert_eq!(child.flagella.len(), 3);
+        assert_eq!(child.eyes.len(), 2);
+        assert_eq!(child.neuron_count(), genome.neuron_count());
+        assert!([genome.split_energy, mate.split_energy].contains(&child.split_energy));
+        assert_eq!(child.flagella[1..], genome.flagella[1..]);
+        // hidden neurons the mate lacks keep their bias
+        for i in child.hidden_neurons() {
+            assert_eq!(child.biases[i], genome.biases[i]);
+        }
+        // unrelated genomes share no connections
+        assert_eq!(child.connections, genome.connections);
+
+        // related ones take shared connections from either parent
+        let child = genome.crossover(&relative, &mut rng);
+        assert_consistent(&child);
+        let mut from_relative = 0;
+        for (connection, own) in child.connections.iter().zip(genome.connections.iter()) {
+            let theirs = relative.connections.iter().find(|c| c.innovation == own.innovation).unwrap();
+            assert!(connection.weight == own.weight || connection.weight == theirs.weight);
+            from_relative += (connection.weight == theirs.weight) as usize;
+        }
+        assert!(from_relative > 0 && from_relative < child.connections.len());
+        assert_eq!(genome.crossover(&genome, &mut rng).connections, genome.connections);
+    }
+
+    #[test]
+    fn test_with_learned() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut innovations = Innovations::default();
+        let mut genome = Genome::random(&mut rng, &mut innovations, 100., 1, 1, 0, 1);
+        genome.split_connection(&mut rng, &mut innovations);
+        let mut learned: Vec<Connection> = genome.connections.iter().filter(|c| c.enabled).copied().collect();
+        for connection in learned.iter_mut() {
+            connection.weight += 1.;
+        }
+
+        let inherited = genome.with_learned(&learned);
+        for (connection, original) in inherited.connections.iter().zip(genome.connections.iter()) {
+            let expected = if original.enabled { original.weight + 1. } else { original.weight };
+            assert_eq!(connection.weight, expected);
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut innovations = Innovations::default();
+        let genome = Genome::random(&mut rng, &mut innovations, 100., 1, 3, 2, 4);
+        let child = genome.mutate(&mut rng, &fixed_structure(), &mut innovations);
+        let stranger = Genome::random(&mut rng, &mut innovations, 300., 4, 1, 5, 0);
+
+        assert_eq!(genome.distance(&genome), 0.);
+        assert_eq!(genome.distance(&child), child.distance(&genome));
+        assert!(genome.distance(&child) > 0.);
+        assert!(genome.distance(&child) < genome.distance(&stranger));
+    }
+}
@@ -0,0 +1,171 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::game_logic::config::*;
+use super::*;
+
+/// Opacity of the glow around a cell in a light of 1.
+const GLOW_ALPHA: f32 = 50. / 255.;
+/// Light beyond which the glow stops getting brighter.
+const MAX_GLOW: f32 = 4.;
+
+/// The glow sprite around a cell, brightened by the light it sits in.
+#[derive(Component)]
+pub struct LightGlow;
+
+impl LightConfig {
+    /// Light at `position` at simulated time `time`.
+    pub fn sample(&self, position: Vec2, time: f32, world: &WorldShape) -> f32 {
+        let light: f32 = self.ambient + self.sources.iter()
+            .map(|source| source.sample(position, world))
+            .sum::<f32>();
+        let daylight = self.day.map_or(1., |day| day.daylight(time));
+        light.max(0.) * daylight
+    }
+}
+
+impl LightSource {
+    /// Light this source adds at `position`. On a torus every source repeats with the
+    /// world, so the light is continuous across the seam.
+    pub fn sample(&self, position: Vec2, world: &WorldShape) -> f32 {
+        let torus = match *world {
+            WorldShape::Torus { half_size } => Some(half_size),
+            _ => None,
+        };
+        match *self {
+            // a gradient can only repeat as a triangle wave, it keeps its slope over the
+            // middle half of the world and runs back down towards the seam
+            Self::Gradient { slope } => slope.dot(torus.map_or(position, |half_size| Vec2::new(
+                fold(position.x, half_size.x),
+                fold(position.y, half_size.y),
+            ))),
+            Self::Spot { center, sigma, intensity } =>
+                intensity * (-world.offset(center, position).length_squared() / (2. * sigma * sigma)).exp(),
+            Self::Noise { seed, scale, intensity } => intensity * match torus {
+                // the scale is rounded so a whole number of lattice cells spans the world
+                Some(half_size) => {
+                    let cells = (2. * half_size / scale).round().max(Vec2::ONE);
+                    value_noise((position + half_size) / (2. * half_size) * cells, seed, Some(cells.as_ivec2()))
+                },
+                None => value_noise(position / scale, seed, None),
+            },
+        }
+    }
+}
+
+impl DayCycle {
+    /// Fraction of the full light at simulated time `time`.
+    pub fn daylight(&self, time: f32) -> f32 {
+        let sun = 0.5 + 0.5 * (2. * PI * time / self.period).cos();
+        self.night + (1. - self.night) * sun
+    }
+}
+
+/// `x` on `[-half / 2, half / 2]`, mirrored beyond so it repeats every `2 * half`.
+fn fold(x: f32, half: f32) -> f32 {
+    let from_peak = (x + half / 2.).rem_euclid(2. * half) - half;
+    half / 2. - from_peak.abs()
+}
+
+/// Random values in `[0, 1)` on the integer lattice, smoothly interpolated in between.
+/// With a `period` the lattice repeats after that many points along each axis.
+fn value_noise(position: Vec2, seed: u64, period: Option<IVec2>) -> f32 {
+    let corner = position.floor();
+    let t = position - corner;
+    let t = t * t * (3. - 2. * t);
+    let point = |x: i64, y: i64| match period {
+        Some(period) => lattice(x.rem_euclid(period.x as i64), y.rem_euclid(period.y as i64), seed),
+        None => lattice(x, y, seed),
+    };
+    let (x, y) = (corner.x as i64, corner.y as i64);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let bottom = lerp(point(x, y), point(x + 1, y), t.x);
+    let top = lerp(point(x, y + 1), point(x + 1, y + 1), t.x);
+    lerp(bottom, top, t.y)
+}
+
+/// Hashes a lattice point with splitmix64.
+fn lattice(x: i64, y: i64, seed: u64) -> f32 {
+    let mut z = seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+pub fn light_glow(
+    cell_query: Query<(&Transform, &CellSprites), With<Cell>>,
+    mut glow_query: Query<&mut Sprite, With<LightGlow>>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
+) {
+    let time = **tick as f32 * FIXED_DELTA;
+    for (transform, sprites) in cell_query.iter() {
+        let light = config.light.sample(transform.translation.truncate(), time, &config.world.shape);
+        for sprite in sprites.iter() {
+            if let Ok(mut glow) = glow_query.get_mut(*sprite) {
+                glow.color.set_a(GLOW_ALPHA * light.min(MAX_GLOW));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light() {
+        assert_eq!(LightConfig::default().sample(Vec2::new(1e4, -3.), 123., &WorldShape::Unbounded), 1.);
+
+        let world = WorldShape::Unbounded;
+        let light = LightConfig {
+            ambient: 0.,
+            sources: vec![
+                LightSource::Gradient { slope: Vec2::new(0.01, 0.) },
+                LightSource::Spot { center: Vec2::new(0., 100.), sigma: 10., intensity: 2. },
+            ],
+            day: Some(DayCycle { period: 10., night: 0.2 }),
+        };
+        assert!((light.sample(Vec2::new(100., 0.), 0., &world) - 1.).abs() < 1e-4);
+        assert!((light.sample(Vec2::new(0., 100.), 0., &world) - 2.).abs() < 1e-4);
+        // never negative, and dimmed at midnight
+        assert_eq!(light.sample(Vec2::new(-100., 0.), 0., &world), 0.);
+        assert!((light.sample(Vec2::new(0., 100.), 5., &world) - 0.4).abs() < 1e-4);
+
+        let noise = LightSource::Noise { seed: 7, scale: 50., intensity: 3. };
+        let samples: Vec<f32> = (0..100).map(|i| noise.sample(Vec2::new(i as f32 * 13., -(i as f32) * 7.), &world)).collect();
+        assert!(samples.iter().all(|s| (0. ..3.).contains(s)));
+        assert!(samples.iter().any(|s| (s - samples[0]).abs() > 0.1));
+        // and smooth at a small scale
+        let p = Vec2::new(123.4, 56.7);
+        assert!((noise.sample(p, &world) - noise.sample(p + 0.1, &world)).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_torus_light() {
+        let world = WorldShape::Torus { half_size: Vec2::new(100., 50.) };
+        let sources = [
+            LightSource::Gradient { slope: Vec2::new(0.01, 0.02) },
+            LightSource::Spot { center: Vec2::new(90., 0.), sigma: 20., intensity: 2. },
+            LightSource::Noise { seed: 3, scale: 30., intensity: 1. },
+        ];
+        // every source is continuous across the seam
+        for source in &sources {
+            for y in [-50., -20., 0., 35.] {
+                let (left, right) = (source.sample(Vec2::new(-100., y), &world), source.sample(Vec2::new(100., y), &world));
+                assert!((left - right).abs() < 1e-4, "{:?} at y {}", source, y);
+            }
+            for x in [-100., -10., 60.] {
+                let (bottom, top) = (source.sample(Vec2::new(x, -50.), &world), source.sample(Vec2::new(x, 50.), &world));
+                assert!((bottom - top).abs() < 1e-4, "{:?} at x {}", source, x);
+            }
+        }
+        // the gradient keeps its slope around the origin and the spot reaches across the seam
+        assert!((sources[0].sample(Vec2::new(20., 10.), &world) - 0.4).abs() < 1e-4);
+        assert!((sources[1].sample(Vec2::new(-90., 0.), &world) - sources[1].sample(Vec2::new(70., 0.), &world)).abs() < 1e-4);
+    }
+}
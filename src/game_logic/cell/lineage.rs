@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::game_logic::config::SimulationConfig;
+use super::*;
+
+/// Ancestry of a cell. Ids are unique for the whole run, including across snapshots.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lineage {
+    pub id: u64,
+    pub parent: Option<u64>,
+    pub generation: u32,
+    pub birth_tick: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineageRecord {
+    Birth(Lineage),
+    Death { id: u64, tick: u64 },
+}
+impl LineageRecord {
+    fn tick(&self) -> u64 {
+        match self {
+            Self::Birth(l) => l.birth_tick,
+            Self::Death { tick, .. } => *tick,
+        }
+    }
+
+    fn to_line(self) -> String {
+        match self {
+            Self::Birth(l) => format!(
+                "birth {} {} {} {}",
+                l.id, l.parent.map_or("-".to_string(), |p| p.to_string()), l.generation, l.birth_tick
+            ),
+            Self::Death { id, tick } => format!("death {} {}", id, tick),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["birth", id, parent, generation, tick] => Some(Self::Birth(Lineage {
+                id: id.parse().ok()?,
+                parent: match *parent {
+                    "-" => None,
+                    p => Some(p.parse().ok()?),
+                },
+                generation: generation.parse().ok()?,
+                birth_tick: tick.parse().ok()?,
+            })),
+            ["death", id, tick] => Some(Self::Death { id: id.parse().ok()?, tick: tick.parse().ok()? }),
+            _ => None,
+        }
+    }
+}
+
+/// A cell of the phylogenetic tree, kept while it or one of its descendants lives.
+struct LineageNode {
+    lineage: Lineage,
+    alive: bool,
+    children: Vec<u64>,
+}
+
+/// The tree of living cells and their ancestors, hands out the cell ids. Births and
+/// deaths are also queued as records for the append-only log.
+#[derive(Resource, Default)]
+pub struct Lineages {
+    next_id: u64,
+    nodes: HashMap<u64, LineageNode>,
+    pending: Vec<LineageRecord>,
+}
+
+impl Lineages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads back a log written by an earlier run, so a run resumed at `tick` keeps its
+    /// history. Records from `tick` on belong to a future that the resumed run replaces,
+    /// they are cut from the file.
+    pub fn load(path: impl AsRef<Path>, tick: u64) -> Result<Self, LineageError> {
+        let path = path.as_ref();
+        let io_error = |e| LineageError::Io(path.to_path_buf(), e);
+        let mut records: Vec<LineageRecord> = BufReader::new(File::open(path).map_err(io_error)?)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| LineageRecord::from_line(&line))
+            .collect();
+        let count = records.len();
+        records.retain(|record| record.tick() < tick);
+        if records.len() < count {
+            let mut text = String::new();
+            for record in &records {
+                writeln!(text, "{}", record.to_line()).unwrap();
+            }
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, text).map_err(|e| LineageError::Io(tmp_path.clone(), e))?;
+            fs::rename(&tmp_path, path).map_err(io_error)?;
+        }
+
+        let mut lineages = Self::new();
+        for record in records {
+            match record {
+                LineageRecord::Birth(lineage) => lineages.insert(lineage),
+                LineageRecord::Death { id, .. } => lineages.remove(id),
+            }
+        }
+        Ok(lineages)
+    }
+
+    pub fn birth(&mut self, parent: Option<&Lineage>, tick: u64) -> Lineage {
+        let lineage = Lineage {
+            id: self.next_id,
+            parent: parent.map(|p| p.id),
+            generation: parent.map_or(0, |p| p.generation + 1),
+            birth_tick: tick,
+        };
+        self.insert(lineage);
+        self.pending.push(LineageRecord::Birth(lineage));
+        lineage
+    }
+
+    /// Registers a cell restored from a snapshot without logging a second birth.
+    pub fn restore(&mut self, lineage: &Lineage) {
+        if !self.nodes.contains_key(&lineage.id) {
+            self.insert(*lineage);
+        }
+    }
+
+    pub fn death(&mut self, lineage: &Lineage, tick: u64) {
+        self.remove(lineage.id);
+        self.pending.push(LineageRecord::Death { id: lineage.id, tick });
+    }
+
+    /// Records queued since the last call, oldest first.
+    pub fn take_records(&mut self) -> Vec<LineageRecord> {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn insert(&mut self, lineage: Lineage) {
+        self.next_id = self.next_id.max(lineage.id + 1);
+        if let Some(parent) = lineage.parent.and_then(|p| self.nodes.get_mut(&p)) {
+            parent.children.push(lineage.id);
+        }
+        self.nodes.insert(lineage.id, LineageNode { lineage, alive: true, children: Vec::new() });
+    }
+
+    /// Marks a cell dead and prunes it, and then every ancestor, once no descendant lives.
+    fn remove(&mut self, id: u64) {
+        let Some(node) = self.nodes.get_mut(&id) else {
+            return;
+        };
+        node.alive = false;
+        let mut current = id;
+        while let Some(node) = self.nodes.get(&current) {
+            if node.alive || !node.children.is_empty() {
+                break;
+            }
+            let parent = node.lineage.parent;
+            self.nodes.remove(&current);
+            let Some(parent) = parent.and_then(|p| self.nodes.get_mut(&p).map(|node| (p, node))) else {
+                break;
+            };
+            parent.1.children.retain(|child| *child != current);
+            current = parent.0;
+        }
+    }
+
+    /// The phylogenetic tree of the living cells in Newick format. Every cell is a node
+    /// labelled `c<id>`, branch lengths are the seconds between the births of parent and
+    /// child. Separate founders are joined under an unnamed root.
+    pub fn to_newick(&self) -> String {
+        let mut roots: Vec<u64> = self.nodes.values()
+            .filter(|node| !node.lineage.parent.is_some_and(|p| self.nodes.contains_key(&p)))
+            .map(|node| node.lineage.id)
+            .collect();
+        roots.sort_unstable();
+        let children = |id: u64| {
+            let mut children = self.nodes[&id].children.clone();
+            children.sort_unstable();
+            children
+        };
+
+        // iterative, lineages get far too deep for recursion
+        let mut out = String::new();
+        let mut stack: Vec<(u64, bool)> = roots.iter().rev().map(|r| (*r, false)).collect();
+        let mut first_sibling = vec![true];
+        if roots.len() > 1 {
+            out.push('(');
+        }
+        while let Some((id, closing)) = stack.pop() {
+            let node = &self.nodes[&id];
+            if closing {
+                first_sibling.pop();
+                if !node.children.is_empty() {
+                    out.push(')');
+                }
+                write!(out, "c{}", id).unwrap();
+                if let Some(parent) = node.lineage.parent.and_then(|p| self.nodes.get(&p)) {
+                    let length = (node.lineage.birth_tick - parent.lineage.birth_tick) as f32 * FIXED_DELTA;
+                    write!(out, ":{}", length).unwrap();
+                }
+                continue;
+            }
+            if !std::mem::replace(first_sibling.last_mut().unwrap(), false) {
+                out.push(',');
+            }
+            stack.push((id, true));
+            first_sibling.push(true);
+            if !node.children.is_empty() {
+                out.push('(');
+                stack.extend(children(id).into_iter().rev().map(|c| (c, false)));
+            }
+        }
+        if roots.len() > 1 {
+            out.push(')');
+        }
+        out.push(';');
+        out
+    }
+}
+
+#[derive(Debug)]
+pub enum LineageError {
+    Io(PathBuf, std::io::Error),
+    /// A fresh run would mix its history into the log of an earlier one.
+    Exists(PathBuf),
+}
+impl fmt::Display for LineageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not access lineage log {}: {}", path.display(), e),
+            Self::Exists(path) => write!(f, "lineage log {} already exists, move it away or resume with --load", path.display()),
+        }
+    }
+}
+impl std::error::Error for LineageError {}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct LineageExportTimer(pub Timer);
+
+pub fn lineage_deaths(
+    mut lineages: ResMut<Lineages>,
+    mut cell_despawn_event_reader: EventReader<CellDespawnEvent>,
+    cell_query: Query<&Lineage>,
+    tick: Res<SimulationTick>,
+) {
+    for cell_entity in cell_despawn_event_reader.read() {
+        if let Ok(lineage) = cell_query.get(**cell_entity) {
+            lineages.death(lineage, **tick);
+        }
+    }
+}
+
+pub fn lineage_export(
+    mut lineages: ResMut<Lineages>,
+    mut timer: ResMut<LineageExportTimer>,
+    config: Res<SimulationConfig>,
+) {
+    let records = lineages.take_records();
+    if let Some(path) = config.lineage.log_path.as_ref().filter(|_| !records.is_empty()) {
+        if let Err(e) = append_records(path, &records) {
+            error!("could not write lineage log {}: {}", path.display(), e);
+        }
+    }
+
+    let Some(path) = &config.lineage.newick_path else {
+        return;
+    };
+    if config.lineage.export_interval <= 0. {
+        return;
+    }
+    timer.tick(Duration::from_secs_f32(FIXED_DELTA));
+    if !timer.just_finished() {
+        return;
+    }
+    if let Err(e) = fs::write(path, lineages.to_newick()) {
+        error!("could not export phylogeny to {}: {}", path.display(), e);
+    }
+}
+
+fn append_records(path: &Path, records: &[LineageRecord]) -> std::io::Result<()> {
+    let mut text = String::new();
+    for record in records {
+        writeln!(text, "{}", record.to_line()).unwrap();
+    }
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newick() {
+        let mut lineages = Lineages::new();
+        let root = lineages.birth(None, 0);
+        let a = lineages.birth(Some(&root), 60);
+        let b = lineages.birth(Some(&root), 60);
+        let _c = lineages.birth(Some(&a), 120);
+        lineages.death(&root, 60);
+        assert_eq!(lineages.to_newick(), "((c3:1)c1:1,c2:1)c0;");
+
+        let _other = lineages.birth(None, 0);
+        assert_eq!(lineages.to_newick(), "(((c3:1)c1:1,c2:1)c0,c4);");
+        assert_eq!(b.generation, 1);
+    }
+
+    #[test]
+    fn test_pruning() {
+        let mut lineages = Lineages::new();
+        let root = lineages.birth(None, 0);
+        let a = lineages.birth(Some(&root), 60);
+        let _b = lineages.birth(Some(&root), 60);
+        let c = lineages.birth(Some(&a), 120);
+        lineages.death(&root, 120);
+        lineages.death(&a, 120);
+        assert_eq!(lineages.to_newick(), "((c3:1)c1:1,c2:1)c0;");
+        // the dead branch goes once its last cell dies
+        lineages.death(&c, 180);
+        assert_eq!(lineages.to_newick(), "(c2:1)c0;");
+        assert_eq!(lineages.nodes.len(), 2);
+        assert_eq!(lineages.take_records().len(), 7);
+        assert!(lineages.take_records().is_empty());
+    }
+
+    #[test]
+    fn test_load_truncates() {
+        let path = std::env::temp_dir().join(format!("lineage-test-{}.log", std::process::id()));
+        let mut lineages = Lineages::new();
+        let root = lineages.birth(None, 0);
+        let a = lineages.birth(Some(&root), 60);
+        lineages.death(&root, 100);
+        let _b = lineages.birth(Some(&a), 200);
+        append_records(&path, &lineages.take_records()).unwrap();
+
+        // the resumed run simulates tick 200 again
+        let mut loaded = Lineages::load(&path, 200).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert_eq!(loaded.to_newick(), "(c1:1)c0;");
+        // ids are handed out again after the snapshot
+        assert_eq!(loaded.birth(Some(&a), 200).id, 2);
+    }
+
+    #[test]
+    fn test_record_lines() {
+        let lineage = Lineage { id: 7, parent: Some(3), generation: 2, birth_tick: 99 };
+        let records = [
+            LineageRecord::Birth(lineage),
+            LineageRecord::Birth(Lineage { parent: None, ..lineage }),
+            LineageRecord::Death { id: 7, tick: 120 },
+        ];
+        for record in records {
+            assert_eq!(LineageRecord::from_line(&record.to_line()), Some(record));
+        }
+    }
+}
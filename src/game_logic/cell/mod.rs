@@ -3,9 +3,23 @@ mod components;
 mod resources;
 mod events;
 mod spawn;
+mod snapshot;
+mod genome;
+mod brain;
+mod lineage;
+mod food;
+mod population;
+mod light;
 
 pub use plugin::*;
 pub use components::*;
 pub use resources::*;
 pub use events::*;
-pub use spawn::*;
\ No newline at end of file
+pub use spawn::*;
+pub use snapshot::*;
+pub use genome::*;
+pub use brain::*;
+pub use lineage::*;
+pub use food::*;
+pub use population::*;
+pub use light::*;
\ No newline at end of file
@@ -1,36 +1,31 @@
 use std::cell;
-use std::f32::consts::PI;
 use std::time::Duration;
 
 use bevy::ecs::query::BatchingStrategy;
+use bevy::ecs::schedule::ExecutorKind;
 use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
 use bevy_rapier2d::prelude::*;
 use ndarray::s;
-use rand_distr::{Normal, Distribution};
-use rand;
-use ndarray::{Array1, Array2};
-use ndarray_rand::RandomExt;
+use ndarray::Array1;
+use rand::Rng;
 use bevy_prototype_lyon::prelude::*;
 
+use crate::game_logic::config::*;
 use crate::game_logic::math::*;
+use crate::game_logic::obstacle::Obstacle;
+use crate::game_logic::physics::{Velocity, AngularVelocity};
 use crate::game_logic::sprites::*;
 
 use super::*;
 
-//pub const SPLIT_ENERGY: f32 = 200.;
-pub const MIN_ENERGY: f32 = 5.;
-
 pub const FIXED_DELTA: f32 = 1./60.;
-
-pub const MUTATION_RATE: f32 = 0.01;
-pub const WEIGHT_MUTATION_RATE: f32 = 0.1;
-
-pub const ENERGY_PENALTY: f32 = 0.01;
-pub const CHLOROPLAST_PRODUCTION: f32 = 1.;
-
-pub const INTERCELL_PUSH: f32 = 1.;
-
-pub const MAX_CELL_COUNT: usize = 2000;
+/// Speed at which the velocity sensors are at about three quarters of their range.
+const SENSED_SPEED: f32 = 100.;
+/// Age at which the age sensor is at about three quarters of its range, in seconds.
+const SENSED_AGE: f32 = 60.;
+/// Period of the clock sensor, in seconds.
+const CLOCK_PERIOD: f32 = 1.;
 
 pub struct CellCorePlugin;
 impl Plugin for CellCorePlugin {
@@ -43,6 +38,11 @@ impl Plugin for CellCorePlugin {
             .add_event::<FoodSpawnEvent>()
             .add_event::<FoodDespawnEvent>()
             .init_resource::<CellCount>()
+            .init_resource::<PopulationStats>()
+            .init_resource::<SimulationConfig>()
+            .init_resource::<SimRng>()
+            .init_resource::<SimulationTick>()
+            .init_resource::<Mates>()
             .add_systems(Startup, resource_init)
             .add_systems(Update, (
                 count_cells,
@@ -59,18 +59,47 @@ impl Plugin for CellCorePlugin {
 pub struct CellServerPlugin;
 impl Plugin for CellServerPlugin {
     fn build(&self, app: &mut App) {
+        let deterministic = app.world.get_resource::<SimulationConfig>()
+            .is_some_and(|config| config.deterministic);
+        if deterministic {
+            // advance exactly one fixed tick per frame and never run fixed systems in parallel
+            let tick = Duration::from_secs_f32(FIXED_DELTA);
+            app
+                .insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+                .insert_resource(Time::<Fixed>::from_duration(tick))
+                .edit_schedule(FixedUpdate, |schedule| {
+                    schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+                });
+        }
+
+        let (snapshot_interval, export_interval) = app.world.get_resource::<SimulationConfig>()
+            .map_or((0., 0.), |config| (config.snapshot.interval, config.lineage.export_interval));
+
         app
+            .init_resource::<Lineages>()
+            .init_resource::<Innovations>()
+            .init_resource::<FoodBacklog>()
+            .insert_resource(SnapshotTimer(Timer::from_seconds(snapshot_interval, TimerMode::Repeating)))
+            .insert_resource(LineageExportTimer(Timer::from_seconds(export_interval, TimerMode::Repeating)))
             .add_systems(Startup, (
                 cell_setup,
+                snapshot_load,
             ))
             .add_systems(FixedUpdate, (
-                //food_spawning,
-                //cell_food_intersection.before(update_radius),
+                food_spawning,
+                cell_food_intersection.before(update_energy),
+                cell_predation.after(cell_food_intersection).before(update_energy),
+                corpse_decay.before(cell_food_intersection),
                 eye_sensing,
                 cell_thinking,
                 update_flagellum.after(cell_thinking),
                 update_energy.before(update_radius),
-                split_cells,
+                find_mates.after(update_energy),
+                split_cells.after(find_mates),
+                snapshot_autosave.after(split_cells),
+                (lineage_deaths, lineage_export, advance_tick).chain()
+                    .after(update_energy)
+                    .after(snapshot_autosave),
             ));  
     }
 }
@@ -87,12 +116,15 @@ impl Plugin for CellClientPlugin {
 pub struct CellCount(pub usize);
 
 pub fn resource_init(mut commands: Commands) {
-    commands.insert_resource(FoodTimer(Timer::new(Duration::from_secs_f32(0.05), TimerMode::Repeating)));
     commands.insert_resource(DebugTimer(Timer::new(Duration::from_secs_f32(1.), TimerMode::Repeating)));
     commands.insert_resource(TimeCounter(0., 0.));
     commands.insert_resource(DelayedDespawnQueue::new());
 }
 
+pub fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    **tick += 1;
+}
+
 pub fn delayed_despawn(
     mut commands: Commands, 
     mut despawn_queue: ResMut<DelayedDespawnQueue>
@@ -112,47 +144,58 @@ pub fn cell_setup(
     flagellum_sprite: Option<Res<FlagellumSprite>>,
     eye_sprite: Option<Res<EyeSprite>>,
     mut cell_count: ResMut<CellCount>,
+    mut rng: ResMut<SimRng>,
+    mut lineages: ResMut<Lineages>,
+    mut innovations: ResMut<Innovations>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
 ) {
+    // the world is rebuilt by snapshot_load instead
+    if config.snapshot.load.is_some() {
+        return;
+    }
     //let normal = Normal::new(0., 10000.).unwrap();
-    //let mut rng = rand::thread_rng();
     
-    spawn_cell(
+    let founder = spawn_cell(
         &mut commands,
         &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
         Vec3::new(0., 0., 0.),
         Quat::from_rotation_z(0.),
-        5., 10.,
-        1,
-        vec![],
-        vec![],
-        Array2::random((0,0), Normal::new(0., 0.5).unwrap()),
-        Array1::random(0, Normal::new(0., 0.5).unwrap()),
-        Array1::random(0, Normal::new(0., 0.5).unwrap()),
+        5.,
+        Genome::random(&mut **rng, &mut innovations, 10., 1, 0, 0, 0),
+        None,
         cell_sprite.as_deref(),
         light_sprite.as_deref(),
         flagellum_sprite.as_deref(),
         eye_sprite.as_deref(),
         cell_count.as_mut(),
     );
+    commands.entity(founder).insert(lineages.birth(None, **tick));
 
     /* 
     for _ in 0..20 {
+        let genome = Genome {
+            split_energy: 200.,
+            chloroplasts: 0,
+            mouth: 0.,
+            flagella: vec![(PI/2., -PI/4.), (0., 0.), (-PI/2.,  PI/4.)],
+            eyes: vec![PI*5.2/6., PI, PI*6.8/6.],
+            weights: Array2::random_using((100,100), Normal::new(0., 0.5).unwrap(), &mut **rng),
+            biases: Array1::random_using(100, Normal::new(0., 0.5).unwrap(), &mut **rng),
+        };
         spawn_cell(
             &mut commands,
             &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
-            Vec3::new(normal.sample(&mut rng), normal.sample(&mut rng),0.),
+            Vec3::new(normal.sample(&mut **rng), normal.sample(&mut **rng),0.),
             Quat::from_rotation_z(0.),
-            100., 200.,
-            0,
-            vec![(PI/2., -PI/4.), (0., 0.), (-PI/2.,  PI/4.)],
-            vec![PI*5.2/6., PI, PI*6.8/6.],
-            Array2::random((100,100), Normal::new(0., 0.5).unwrap()),
-            Array1::random(100, Normal::new(0., 0.5).unwrap()),
-            Array1::random(100, Normal::new(0., 0.5).unwrap()),
+            100.,
+            genome,
+            Array1::random_using(100, Normal::new(0., 0.5).unwrap(), &mut **rng),
             cell_sprite.as_deref(),
             light_sprite.as_deref(),
             flagellum_sprite.as_deref(),
             eye_sprite.as_deref(),
+            cell_count.as_mut(),
         );
     }
     */
@@ -161,7 +204,8 @@ pub fn cell_setup(
         spawn_food(
             &mut commands, 
             &mut food_spawn_event_writer,
-            Vec3::new(normal.sample(&mut rng), normal.sample(&mut rng), 0.),
+            Vec3::new(normal.sample(&mut **rng), normal.sample(&mut **rng), 0.),
+            10.,
             food_sprite.as_deref(),
             light_sprite.as_deref(),
         );
@@ -169,49 +213,22 @@ pub fn cell_setup(
     */
 }
 
-pub fn cell_food_intersection(
-    mut despawn_queue: ResMut<DelayedDespawnQueue>,
-    mut cell_query: Query<(&mut Energy, &CellCollider), With<Cell>>,
-    collider_query: Query<(&Collider, &GlobalTransform)>,
-    mut food_query: Query<&mut Dead, With<Food>>,
-    rapier_context: Res<RapierContext>,
-    mut food_despawn_event_writer: EventWriter<FoodDespawnEvent>,
-) {
-    for (mut energy, collider_entity) in cell_query.iter_mut() {
-        if let Ok((collider, transform)) = collider_query.get(**collider_entity) {
-            let direction = quat_to_direction(transform.to_scale_rotation_translation().1);
-            let angle = (-direction.x).atan2(direction.y);
-            rapier_context.intersections_with_shape(
-                transform.translation().truncate(), 
-                angle, 
-                collider, 
-                QueryFilter::default(), 
-                |x| {
-                    if let Ok(mut eaten) = food_query.get_mut(x) {
-                        if !**eaten {
-                            **eaten = true;
-                            despawn_food(&mut despawn_queue, &mut food_despawn_event_writer, x);
-                            **energy += 10.
-                        }
-                    }
-                    true
-                }
-            )
-        }
-    }
-}
-
 pub fn eye_sensing(
-    mut eye_query: Query<(&Parent, &mut Activation, &GlobalTransform, &Collider, &ViewParams), With<Eye>>,
+    mut eye_query: Query<(&Parent, &mut Activation, &mut Vision, &GlobalTransform, &Collider, &ViewParams), With<Eye>>,
     collider_query: Query<&Parent, With<CellColliderTag>>,
     cell_query: Query<(&Transform, &Radius), With<Cell>>,
+    food_query: Query<(&Transform, &Dead), With<Food>>,
+    obstacle_query: Query<&Collider, With<Obstacle>>,
     rapier_context: Res<RapierContext>,
+    config: Res<SimulationConfig>,
 ) {
     eye_query
         .par_iter_mut()
         .batching_strategy(BatchingStrategy::new().min_batch_size(32))
-        .for_each(|(parent, mut eye_activation, eye_transform, collider, view_params)| {
-            let mut activation: f32 = 0.;
+        .for_each(|(parent, mut eye_activation, mut eye_vision, eye_transform, collider, view_params)| {
+            let mut vision = Vision::default();
+            let own_radius = cell_query.get(parent.get()).map_or(0., |(_, radius)| **radius);
+            let mut closest_cell = 0.;
             let direction = quat_to_direction(eye_transform.to_scale_rotation_translation().1);
             let angle = (-direction.x).atan2(direction.y);
 
@@ -223,54 +240,121 @@ pub fn eye_sensing(
                 view_params.n_normal.x*direction.y + view_params.n_normal.y*direction.x, 
                 - view_params.n_normal.x*direction.x + view_params.n_normal.y*direction.y
             );
-
-            rapier_context.intersections_with_shape(
-                eye_transform.translation().truncate(), 
-                angle, 
-                collider, 
-                QueryFilter::default(), 
-                |x| {
-                    if let Ok(cell) = collider_query.get(x) {
-                        if parent.get() == cell.get() {
-                            return true;
+            let closeness = |distance: f32| (1.-distance/view_params.range).clamp(0., 1.);
+
+            for shift in config.world.shape.shifts(eye_transform.translation().truncate(), view_params.range) {
+                let eye_position = eye_transform.translation().truncate() + shift;
+                rapier_context.intersections_with_shape(
+                    eye_position, 
+                    angle, 
+                    collider, 
+                    QueryFilter::default(), 
+                    |x| {
+                        if let Ok(cell) = collider_query.get(x) {
+                            if parent.get() == cell.get() {
+                                return true;
+                            }
+                            if let Ok((cell_transform, radius)) = cell_query.get(cell.get()) {
+                                let center = cell_transform.translation.truncate() - eye_position;
+                                if let Some(point) = nearest_intersection(center, **radius, m, n) {
+                                    let cell_closeness = closeness(point.length());
+                                    vision.see(EyeChannel::Cell, cell_closeness);
+                                    if cell_closeness > closest_cell {
+                                        closest_cell = cell_closeness;
+                                        vision[EyeChannel::CellSize as usize] = **radius / (**radius + own_radius);
+                                    }
+                                } 
+                            }
                         }
-                        if let Ok((cell_transform, radius)) = cell_query.get(cell.get()) {
-                            let center = cell_transform.translation.truncate() - eye_transform.translation().truncate();
-                            if let Some(point) = nearest_intersection(center, **radius, m, n) {
-                                activation = activation.max((1.-point.length()/view_params.range).max(0.).min(1.));
-                            } 
+                        else if let Ok((food_transform, dead)) = food_query.get(x) {
+                            if !**dead {
+                                let center = food_transform.translation.truncate() - eye_position;
+                                if let Some(point) = nearest_intersection(center, FOOD_RADIUS, m, n) {
+                                    vision.see(EyeChannel::Food, closeness(point.length()));
+                                }
+                            }
                         }
+                        else if let Ok(obstacle) = obstacle_query.get(x) {
+                            // the closest point of the obstacle if it lies in the field of view,
+                            // otherwise the closest one is on one of its edges
+                            let point = obstacle.project_point(Vec2::ZERO, 0., eye_position, true).point - eye_position;
+                            let distance = if point.dot(m) >= 0. && point.dot(n) >= 0. {
+                                Some(point.length())
+                            } else {
+                                let edges = [Vec2::new(-m.y, m.x), Vec2::new(n.y, -n.x)];
+                                edges.into_iter()
+                                    .filter_map(|edge| obstacle.cast_ray(Vec2::ZERO, 0., eye_position, edge, view_params.range, true))
+                                    .reduce(f32::min)
+                            };
+                            if let Some(distance) = distance {
+                                vision.see(EyeChannel::Obstacle, closeness(distance));
+                            }
+                        }
+                        true
                     }
-                    true
-                }
-            );
+                );
+            }
 
-            **eye_activation = activation;
+            **eye_activation = [EyeChannel::Cell, EyeChannel::Food, EyeChannel::Obstacle].iter()
+                .map(|channel| vision[*channel as usize])
+                .fold(0., f32::max);
+            *eye_vision = vision;
     });
 }
 
 pub fn cell_thinking(
-    mut cell_query: Query<(&mut NeuronState, &NeuronWeights, &NeuronBiases, &mut ThinkingTimer, &CellEyes, &CellFlagella)>,
-    eye_query: Query<&Activation, With<Eye>>,
+    mut cell_query: Query<(
+        &mut NeuronState, &mut NeuronConnections, &Genome, &mut ThinkingTimer, &CellEyes,
+        &mut Energy, &mut PreviousEnergy, &Transform, &Velocity, &AngularVelocity, &Lineage,
+    )>,
+    eye_query: Query<&Vision, With<Eye>>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
 ) {
+    let brain = config.brain.brain();
+    let time = **tick as f32 * FIXED_DELTA;
     cell_query.par_iter_mut()
         .batching_strategy(BatchingStrategy::new().min_batch_size(100))
-        .for_each(|(mut state, weights, biases, mut timer, eyes, flagella)| {
+        .for_each(|(mut state, mut connections, genome, mut timer, eyes, mut energy, mut previous_energy, transform, velocity, angular_velocity, lineage)| {
             timer.tick(Duration::from_secs_f32(FIXED_DELTA));
+            timer.since_update += FIXED_DELTA;
             if timer.finished() {
-                //update eye neuron state from what eyes see
-                let activations: Vec<f32> = eyes.iter().map(|eye| **eye_query.get(*eye).unwrap()).collect();
-                for (i, act) in activations.iter().enumerate() {
-                    state[i] = *act;
+                //update sensor neuron state from the cell's own state
+                let forward = quat_to_direction(transform.rotation);
+                let age = tick.saturating_sub(lineage.birth_tick) as f32 * FIXED_DELTA;
+                state[InternalSensor::Energy as usize] = **energy / genome.split_energy;
+                state[InternalSensor::ForwardVelocity as usize] = (velocity.dot(forward) / SENSED_SPEED).tanh();
+                state[InternalSensor::LateralVelocity as usize] = (velocity.perp_dot(forward) / SENSED_SPEED).tanh();
+                state[InternalSensor::AngularVelocity as usize] = angular_velocity.tanh();
+                state[InternalSensor::Age as usize] = (age / SENSED_AGE).tanh();
+                state[InternalSensor::Clock as usize] = (2. * std::f32::consts::PI * age / CLOCK_PERIOD).sin();
+                state[InternalSensor::Light as usize] = config.light.sample(transform.translation.truncate(), time, &config.world.shape).tanh();
+
+                //update eye neuron state from what eyes see, one neuron per channel
+                for (i, eye) in eyes.iter().enumerate() {
+                    let vision = eye_query.get(*eye).unwrap();
+                    for (c, act) in vision.iter().enumerate() {
+                        state[SENSORS + i * EYE_CHANNELS + c] = *act;
+                    }
                 }
                 
                 //compute state update
-                **state = state.dot(&**weights) + &**biases;
-                let activation_range = s![eyes.len()..state.shape()[0]-flagella.len()];
-                state.slice_mut(activation_range).map_inplace(tanh_inplace);
-                let activation_range = s![state.shape()[0]-flagella.len()..];
-                //state.slice_mut(activation_range).map_inplace(sigmoid_inplace);
-                state.slice_mut(activation_range).map_inplace(tanh_inplace);
+                let dt = std::mem::take(&mut timer.since_update);
+                let before = (config.plasticity.rule != PlasticityRule::Off).then(|| state.clone());
+                let network = Network {
+                    connections: &connections,
+                    biases: &genome.biases,
+                    time_constants: &genome.time_constants,
+                    inputs: SENSORS + eyes.len() * EYE_CHANNELS,
+                };
+                brain.think(&network, &mut state, dt);
+
+                if let Some(before) = before {
+                    let modulation = (**energy - **previous_energy).tanh();
+                    config.plasticity.rule.learn(&mut connections, &before, &state, modulation, dt, config.plasticity.max_weight);
+                }
+                **energy -= (genome.neuron_count() + connections.len()) as f32 * config.metabolism.thinking;
+                **previous_energy = **energy;
             }
         });
 }
@@ -288,15 +372,64 @@ pub fn update_flagellum(
 }
 
 pub fn update_energy(
+    mut commands: Commands,
     mut despawn_queue: ResMut<DelayedDespawnQueue>,
-    mut cell_query: Query<(Entity, &mut Energy, &SplitEnergy, &Chloroplasts), With<Cell>>,
+    mut cell_query: Query<(
+        Entity, &mut Energy, &Genome, &CellFlagella, &NeuronConnections, &Transform, &mut Dead,
+    ), With<Cell>>,
+    flagellum_query: Query<&Activation, With<Flagellum>>,
     mut cell_despawn_event_writer: EventWriter<CellDespawnEvent>,
-    mut cell_count: ResMut<CellCount>
+    mut food_spawn_event_writer: EventWriter<FoodSpawnEvent>,
+    food_sprite: Option<Res<FoodSprite>>,
+    light_sprite: Option<Res<LightSprite>>,
+    mut cell_count: ResMut<CellCount>,
+    mut population: ResMut<PopulationStats>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
 ) {
-    for (cell_entity, mut energy, split_energy, chloroplasts) in cell_query.iter_mut() {
-        **energy += (chloroplasts.0 as f32 * CHLOROPLAST_PRODUCTION - energy.0 * ENERGY_PENALTY) * FIXED_DELTA;
-        if energy.0 < split_energy.0 / 4. {
+    let time = **tick as f32 * FIXED_DELTA;
+    population.upkeep = config.population.upkeep(**cell_count);
+    population.light_share = config.population.light_share(**cell_count);
+    let mut cells: Vec<Entity> = cell_query.iter().map(|(e, ..)| e).collect();
+    if config.deterministic {
+        cells.sort_unstable();
+    }
+    for cell_entity in cells {
+        let (_, mut energy, genome, flagella, connections, transform, mut dead) = cell_query.get_mut(cell_entity).unwrap();
+        if **dead {
+            continue;
+        }
+        let metabolism = &config.metabolism;
+        let strokes: f32 = flagella.iter()
+            .filter_map(|flagellum| flagellum_query.get(*flagellum).ok())
+            .map(|activation| activation.abs())
+            .sum();
+        let upkeep = strokes * metabolism.flagellum
+            + genome.eyes.len() as f32 * metabolism.eye
+            + genome.eyes.iter().map(EyeGene::area).sum::<f32>() * metabolism.vision
+            + genome.neuron_count() as f32 * metabolism.neuron
+            + connections.len() as f32 * metabolism.connection
+            + genome.mouth * metabolism.mouth
+            + population.upkeep;
+        let light = config.light.sample(transform.translation.truncate(), time, &config.world.shape);
+        let production = genome.chloroplasts as f32 * config.chloroplast_production * light * population.light_share;
+        **energy += (production - energy.0 * config.energy_penalty - upkeep) * FIXED_DELTA;
+        if energy.0 < genome.split_energy / 4. {
+            **dead = true;
             despawn_cell(&mut despawn_queue, &mut cell_despawn_event_writer, cell_entity, cell_count.as_mut());
+
+            let remains = energy.0 * config.corpse.fraction;
+            if remains >= config.corpse.min_energy {
+                let corpse = spawn_food(
+                    &mut commands,
+                    &mut food_spawn_event_writer,
+                    transform.translation,
+                    remains,
+                    food_sprite.as_deref(),
+                    light_sprite.as_deref(),
+                );
+                commands.entity(corpse).insert(Corpse);
+            }
         }
     }
 }
@@ -308,72 +441,171 @@ pub fn split_cells(
     mut cell_despawn_event_writer: EventWriter<CellDespawnEvent>,
     mut flagellum_spawn_event_writer: EventWriter<FlagellumSpawnEvent>,
     mut eye_spawn_event_writer: EventWriter<EyeSpawnEvent>,
-    mut cell_query: Query<(Entity, &mut Dead, &Energy, &SplitEnergy, &Chloroplasts, &NeuronWeights, &NeuronBiases, &NeuronState, &FlagellaParams, &EyeParams, &Transform), With<Cell>>,
-    cell_sprite: Option<Res<CellSprite>>,
-    light_sprite: Option<Res<LightSprite>>,
-    flagellum_sprite: Option<Res<FlagellumSprite>>,
-    eye_sprite: Option<Res<EyeSprite>>,
-    mut cell_count: ResMut<CellCount>,
+    mut cell_query: Query<(Entity, &mut Dead, &Energy, &Genome, &NeuronConnections, &NeuronState, &Transform, &Velocity, &AngularVelocity, &Lineage), With<Cell>>,
+    (cell_sprite, light_sprite, flagellum_sprite, eye_sprite): (
+        Option<Res<CellSprite>>,
+        Option<Res<LightSprite>>,
+        Option<Res<FlagellumSprite>>,
+        Option<Res<EyeSprite>>,
+    ),
+    mates: Res<Mates>,
+    (mut cell_count, mut population): (ResMut<CellCount>, ResMut<PopulationStats>),
+    mut rng: ResMut<SimRng>,
+    mut lineages: ResMut<Lineages>,
+    mut innovations: ResMut<Innovations>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
 ) {
-    for (
-        cell_entity, mut dead, 
-        energy, split_energy, chloroplasts, 
-        weights, biases, state, 
-        flagella_params, eye_params, 
-        cell_transform
-    ) in cell_query.iter_mut().filter(
-        |(_, dead, energy, split_energy, _, _, _, _, _, _, _)| energy.0 >= split_energy.0 && !dead.0
-    ) {
+    let mut ready: Vec<Entity> = cell_query.iter()
+        .filter(|(_, dead, energy, genome, _, state, ..)| {
+            !dead.0 && genome.ready_to_split(energy.0, state, config.reproduction.voluntary)
+        })
+        .map(|(e, ..)| e)
+        .collect();
+    // cells the population cap may cull to make room for newborns
+    let mut alive: Vec<Entity> = cell_query.iter()
+        .filter(|(_, dead, ..)| !dead.0)
+        .map(|(e, ..)| e)
+        .collect();
+    if config.deterministic {
+        ready.sort_unstable();
+        alive.sort_unstable();
+    }
+    let heritable = |genome: &Genome, connections: &NeuronConnections| match config.plasticity.inherit_learned {
+        true => genome.with_learned(connections),
+        false => genome.clone(),
+    };
+
+    // cells mutating the same way this tick share innovations, like a generation in NEAT
+    innovations.forget();
+    for cell_entity in ready {
+        // a mate that split earlier this tick is gone already
+        let mate_genome = mates.get(&cell_entity)
+            .and_then(|mate| cell_query.get(*mate).ok())
+            .filter(|(_, dead, ..)| !***dead)
+            .map(|(_, _, _, genome, connections, ..)| heritable(genome, connections));
+
+        let (_, mut dead, energy, genome, connections, state, cell_transform, velocity, angular_velocity, lineage) = cell_query.get_mut(cell_entity).unwrap();
+        // culled earlier this tick
+        if **dead {
+            continue;
+        }
         **dead = true;
+        let genome = &heritable(genome, connections);
 
         let position = cell_transform.translation;
         let rotation = cell_transform.rotation;
-        let (weights, biases, state) = (&**weights, &**biases, &**state);
-        
-        let normal = Normal::new(0., MUTATION_RATE).unwrap();
-        let weight_normal = Normal::new(0., WEIGHT_MUTATION_RATE).unwrap();
-        let mut rng = rand::thread_rng();
+        let axis = quat_to_direction(rotation).extend(0.);
+        let (velocity, angular_velocity) = (**velocity, **angular_velocity);
+        let lineage = *lineage;
+        // the bigger daughter comes first, ahead of its sibling, it is the one kept when the world is full
+        let energies = genome.split_energies(**energy);
+        let layout = config.reproduction.division.layout(energies);
+        let turn = config.reproduction.division.turn;
+        let daughters = [(turn, energies[0], layout[0]), (-turn, energies[1], layout[1])].map(|(turn, energy, layout)| {
+            let daughter = match &mate_genome {
+                Some(mate_genome) => genome.crossover(mate_genome, &mut **rng).mutate(&mut **rng, &config.mutation, &mut innovations),
+                None => genome.mutate(&mut **rng, &config.mutation, &mut innovations),
+            };
+            let state = daughter.inherit_state(genome, state);
+            (turn, energy, layout, daughter, state)
+        });
 
         despawn_cell(&mut despawn_queue, &mut cell_despawn_event_writer, cell_entity, cell_count.as_mut());
-        spawn_cell(&mut commands, 
-            &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
-            position, 
-            rotation * Quat::from_rotation_z(0.1), 
-            **energy/2.,
-            (**split_energy + 10. * normal.sample(&mut rng)).max(MIN_ENERGY*2.),
-            **chloroplasts,
-            flagella_params.iter().map(|(pos, ang)| (pos + normal.sample(&mut rng), (ang + normal.sample(&mut rng)).clamp(-PI/2., PI/2.))).collect(),
-            eye_params.iter().map(|pos| pos + normal.sample(&mut rng)).collect(),
-            weights.map(|x| x + weight_normal.sample(&mut rng)),
-            biases.map(|x| x + weight_normal.sample(&mut rng)),
-            state.clone(),
-            cell_sprite.as_deref(),
-            light_sprite.as_deref(),
-            flagellum_sprite.as_deref(),
-            eye_sprite.as_deref(),
-            cell_count.as_mut(),
+        let mut overflow = config.population.overflow(**cell_count, daughters.len());
+        while overflow > 0 && !alive.is_empty() {
+            let victim = alive.swap_remove(rng.gen_range(0..alive.len()));
+            let Ok((_, mut dead, ..)) = cell_query.get_mut(victim) else {
+                continue;
+            };
+            if **dead {
+                continue;
+            }
+            **dead = true;
+            despawn_cell(&mut despawn_queue, &mut cell_despawn_event_writer, victim, cell_count.as_mut());
+            population.culled += 1;
+            overflow -= 1;
+        }
+        for (i, (turn, energy, (offset, separation), genome, state)) in daughters.into_iter().enumerate() {
+            // with nothing left to cull, the first daughter still takes the place of its parent
+            if i > 0 && config.population.overflow(**cell_count, 1) > 0 {
+                break;
+            }
+            let daughter = spawn_cell(&mut commands, 
+                &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
+                position + axis * offset,
+                rotation * Quat::from_rotation_z(turn),
+                energy,
+                genome,
+                Some(state),
+                cell_sprite.as_deref(),
+                light_sprite.as_deref(),
+                flagellum_sprite.as_deref(),
+                eye_sprite.as_deref(),
+                cell_count.as_mut(),
             );
-        if cell_count.0 >= MAX_CELL_COUNT {
+            commands.entity(daughter).insert((
+                Velocity(velocity + axis.truncate() * separation),
+                AngularVelocity(angular_velocity),
+                lineages.birth(Some(&lineage), **tick),
+            ));
+        }
+    }
+}
+
+/// Pairs every cell ready to split with the closest touching cell it is compatible with.
+pub fn find_mates(
+    mut mates: ResMut<Mates>,
+    collider_query: Query<(&Parent, &Collider), With<CellColliderTag>>,
+    cell_query: Query<(Entity, &Transform, &Radius, &Energy, &Genome, &NeuronState, &CellCollider, &Dead), With<Cell>>,
+    rapier_context: Res<RapierContext>,
+    config: Res<SimulationConfig>,
+) {
+    mates.clear();
+    if !config.reproduction.sexual {
+        return;
+    }
+
+    for (entity, transform, radius, energy, genome, state, cell_collider, dead) in cell_query.iter() {
+        if **dead || !genome.ready_to_split(**energy, state, config.reproduction.voluntary) {
             continue;
         }
-        spawn_cell(&mut commands, 
-            &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
-            position, 
-            rotation * Quat::from_rotation_z(-0.1), 
-            **energy/2., 
-            (**split_energy + 10. * normal.sample(&mut rng)).max(MIN_ENERGY*2.),
-            **chloroplasts,
-            flagella_params.iter().map(|(pos, ang)| (pos + normal.sample(&mut rng), (ang + normal.sample(&mut rng)).clamp(-PI/2., PI/2.))).collect(),
-            eye_params.iter().map(|pos| pos + normal.sample(&mut rng)).collect(),
-            weights.map(|x| x + weight_normal.sample(&mut rng)),
-            biases.map(|x| x + weight_normal.sample(&mut rng)),
-            state.clone(),
-            cell_sprite.as_deref(),
-            light_sprite.as_deref(),
-            flagellum_sprite.as_deref(),
-            eye_sprite.as_deref(),
-            cell_count.as_mut(),
-        );
+        let Ok((_, collider)) = collider_query.get(**cell_collider) else {
+            continue;
+        };
+        let mut closest: Option<(f32, Entity)> = None;
+        for shift in config.world.shape.shifts(transform.translation.truncate(), **radius) {
+            let position = transform.translation.truncate() + shift;
+            rapier_context.intersections_with_shape(
+                position,
+                0.,
+                collider,
+                QueryFilter::default(),
+                |x| {
+                    let Ok((parent, _)) = collider_query.get(x) else {
+                        return true;
+                    };
+                    let Ok((mate, mate_transform, _, _, mate_genome, _, _, mate_dead)) = cell_query.get(parent.get()) else {
+                        return true;
+                    };
+                    if mate == entity || **mate_dead {
+                        return true;
+                    }
+                    if config.reproduction.max_mate_distance.is_some_and(|max| genome.distance(mate_genome) > max) {
+                        return true;
+                    }
+                    // ties broken by entity so the choice does not depend on query order
+                    let candidate = (position.distance_squared(mate_transform.translation.truncate()), mate);
+                    if !closest.is_some_and(|c| c <= candidate) {
+                        closest = Some(candidate);
+                    }
+                    true
+                }
+            );
+        }
+        if let Some((_, mate)) = closest {
+            mates.insert(entity, mate);
+        }
     }
 }
 
@@ -383,7 +615,7 @@ pub fn update_radius(
 ) {
     for (energy, mut radius, cell_flagella, cell_eyes, cell_collider, cell_sprites) in cell_query.iter_mut() {
         let old_radius = radius.0;
-        radius.0 = 5. * energy.0.sqrt();
+        radius.0 = cell_radius(energy.0);
         let ratio = radius.0 / old_radius;
         
         cell_flagella.iter().for_each(|e| {
@@ -407,33 +639,13 @@ pub fn update_radius(
     }
 }
 
-/*
-pub fn food_spawning(
-    mut commands: Commands,
-    mut food_spawn_event_writer: EventWriter<FoodSpawnEvent>,
-    mut timer: ResMut<FoodTimer>,
-    food_sprite: Option<Res<FoodSprite>>,
-    light_sprite: Option<Res<LightSprite>>,
-) {
-    timer.tick(Duration::from_secs_f32(FIXED_DELTA));
-    for _ in 0..timer.times_finished_this_tick() {
-        let normal = Normal::new(0., 15000.).unwrap();
-        let mut rng = rand::thread_rng();
-        
-        spawn_food(
-            &mut commands, 
-            &mut food_spawn_event_writer,
-            Vec3::new(normal.sample(&mut rng), normal.sample(&mut rng), 0.),
-            food_sprite.as_deref(),
-            light_sprite.as_deref(),
-        );
-    }
-}*/
-
-pub fn count_cells(cell_query: Query<&Cell>, food_query: Query<&Food>, mut timer: ResMut<DebugTimer>, time: Res<Time>, cell_count: Res<CellCount>) {
+pub fn count_cells(cell_query: Query<&Cell>, food_query: Query<&Food>, mut timer: ResMut<DebugTimer>, time: Res<Time>, cell_count: Res<CellCount>, population: Res<PopulationStats>, config: Res<SimulationConfig>) {
     timer.tick(time.delta());
     if timer.finished() {
         print!("FPS: {}, cell_count: {}, food count: {}, resource_count: {}\n", (1./time.delta_seconds()).round(), cell_query.iter().count(), food_query.iter().count(), cell_count.0);
+        if let Some(summary) = population.summary(&config.population) {
+            info!("{}", summary);
+        }
     }
 }
 
@@ -448,4 +660,149 @@ pub fn fixed_thing(mut cnter: ResMut<TimeCounter>) {
         cnter.0 -= 1.;
         cnter.1 = 0.;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::game_logic::obstacle::{spawn_obstacle, ObstacleShape};
+
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0),
+        ));
+        app.insert_resource(SimulationConfig::default());
+        app.add_event::<CellSpawnEvent>();
+        app.add_event::<FlagellumSpawnEvent>();
+        app.add_event::<EyeSpawnEvent>();
+        app.add_event::<FoodSpawnEvent>();
+        app.init_resource::<SimRng>();
+        app.init_resource::<CellCount>();
+        app.init_resource::<Lineages>();
+        app.init_resource::<Innovations>();
+        app.init_resource::<SimulationTick>();
+        app
+    }
+
+    /// A cell of radius 10 whose eyes all look down the y axis from its bottom.
+    fn spawn_test_cell(app: &mut App, position: Vec3, eyes: usize) -> Entity {
+        app.world.run_system_once(move |
+            mut commands: Commands,
+            mut cell_spawn_event_writer: EventWriter<CellSpawnEvent>,
+            mut flagellum_spawn_event_writer: EventWriter<FlagellumSpawnEvent>,
+            mut eye_spawn_event_writer: EventWriter<EyeSpawnEvent>,
+            mut cell_count: ResMut<CellCount>,
+            mut rng: ResMut<SimRng>,
+            mut lineages: ResMut<Lineages>,
+            mut innovations: ResMut<Innovations>,
+        | {
+            let mut genome = Genome::random(&mut **rng, &mut innovations, 10., 0, 0, eyes, 0);
+            for eye in genome.eyes.iter_mut() {
+                *eye = EyeGene { position: 0., fov: 1., range: 100. };
+            }
+            let cell = spawn_cell(
+                &mut commands,
+                &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
+                position,
+                Quat::IDENTITY,
+                4.,
+                genome,
+                None,
+                None, None, None, None,
+                cell_count.as_mut(),
+            );
+            commands.entity(cell).insert(lineages.birth(None, 0));
+            cell
+        })
+    }
+
+    /// What the eye of a cell at the origin sees once `target` is placed in front of it.
+    fn sight(target: impl FnOnce(&mut App)) -> Vision {
+        let mut app = test_app();
+        let cell = spawn_test_cell(&mut app, Vec3::ZERO, 1);
+        target(&mut app);
+        // the first update places the colliders, the second one moves them to their transforms
+        app.update();
+        app.update();
+        app.world.run_system_once(eye_sensing);
+        let eye = app.world.get::<CellEyes>(cell).unwrap()[0];
+        *app.world.get::<Vision>(eye).unwrap()
+    }
+
+    #[test]
+    fn test_founder_thinks() {
+        let mut app = test_app();
+        app.world.run_system_once(cell_setup);
+        let founder = app.world.query_filtered::<Entity, With<Cell>>().single(&app.world);
+        app.world.get_mut::<ThinkingTimer>(founder).unwrap().timer = Timer::from_seconds(FIXED_DELTA, TimerMode::Repeating);
+        app.world.run_system_once(cell_thinking);
+
+        let genome = app.world.get::<Genome>(founder).unwrap();
+        let state = app.world.get::<NeuronState>(founder).unwrap();
+        assert_eq!(state.len(), genome.neuron_count());
+        assert_eq!(state[InternalSensor::Energy as usize], 5. / genome.split_energy);
+    }
+
+    #[test]
+    fn test_eye_channels() {
+        let target = Vec3::new(0., -60., 0.);
+
+        assert_eq!(*sight(|_| ()), [0.; EYE_CHANNELS]);
+
+        let cell = sight(|app| { spawn_test_cell(app, target, 0); });
+        assert!(cell[EyeChannel::Cell as usize] > 0.);
+        assert_eq!(cell[EyeChannel::Food as usize], 0.);
+        assert_eq!(cell[EyeChannel::Obstacle as usize], 0.);
+        assert_eq!(cell[EyeChannel::CellSize as usize], 0.5);
+
+        let food = sight(|app| {
+            app.world.run_system_once(move |mut commands: Commands, mut food_spawn_event_writer: EventWriter<FoodSpawnEvent>| {
+                spawn_food(&mut commands, &mut food_spawn_event_writer, target, 10., None, None);
+            });
+        });
+        assert!(food[EyeChannel::Food as usize] > 0.);
+        assert_eq!(food[EyeChannel::Cell as usize], 0.);
+        assert_eq!(food[EyeChannel::Obstacle as usize], 0.);
+        assert_eq!(food[EyeChannel::CellSize as usize], 0.);
+
+        let obstacle = sight(|app| {
+            app.world.run_system_once(move |mut commands: Commands| {
+                spawn_obstacle(&mut commands, &ObstacleShape::Circle { center: target.truncate(), radius: 5. });
+            });
+        });
+        assert!(obstacle[EyeChannel::Obstacle as usize] > 0.);
+        assert_eq!(obstacle[EyeChannel::Cell as usize], 0.);
+        assert_eq!(obstacle[EyeChannel::Food as usize], 0.);
+        assert_eq!(obstacle[EyeChannel::CellSize as usize], 0.);
+    }
+
+    #[test]
+    fn test_eye_inputs() {
+        let mut app = test_app();
+        let cell = spawn_test_cell(&mut app, Vec3::ZERO, 2);
+        let eyes = app.world.get::<CellEyes>(cell).unwrap().0.clone();
+        for (i, eye) in eyes.iter().enumerate() {
+            let mut vision = app.world.get_mut::<Vision>(*eye).unwrap();
+            for c in 0..EYE_CHANNELS {
+                vision[c] = (1 + i * EYE_CHANNELS + c) as f32 / 10.;
+            }
+        }
+        app.world.get_mut::<ThinkingTimer>(cell).unwrap().timer = Timer::from_seconds(FIXED_DELTA, TimerMode::Repeating);
+        app.world.run_system_once(cell_thinking);
+
+        // eye after eye, channel after channel, right between the sensors and the hidden neurons
+        let genome = app.world.get::<Genome>(cell).unwrap();
+        let state = app.world.get::<NeuronState>(cell).unwrap();
+        assert_eq!(genome.eye_neurons(), SENSORS..SENSORS + 2 * EYE_CHANNELS);
+        assert_eq!(
+            state.slice(s![genome.eye_neurons()]).to_vec(),
+            (1..=2 * EYE_CHANNELS).map(|x| x as f32 / 10.).collect::<Vec<_>>(),
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use crate::game_logic::config::PopulationControl;
+
+/// What the population control currently does, logged with the cell count.
+#[derive(Resource, Debug)]
+pub struct PopulationStats {
+    /// Cells culled to make room for newborns since the start.
+    pub culled: usize,
+    /// Extra energy per second every cell loses.
+    pub upkeep: f32,
+    /// Fraction of their usual photosynthesis cells produce.
+    pub light_share: f32,
+}
+impl Default for PopulationStats {
+    fn default() -> Self {
+        Self {
+            culled: 0,
+            upkeep: 0.,
+            light_share: 1.,
+        }
+    }
+}
+
+impl PopulationStats {
+    /// The stats `control` acts on, nothing when the population is unlimited.
+    pub fn summary(&self, control: &PopulationControl) -> Option<String> {
+        match control {
+            PopulationControl::Unlimited => None,
+            PopulationControl::Cap { .. } => Some(format!("culled: {}", self.culled)),
+            PopulationControl::Density { .. } => Some(format!("density upkeep: {:.3}", self.upkeep)),
+            PopulationControl::Carrying { .. } => Some(format!("light share: {:.2}", self.light_share)),
+        }
+    }
+}
+
+impl PopulationControl {
+    /// Extra energy per second every cell loses with `count` cells alive.
+    pub fn upkeep(&self, count: usize) -> f32 {
+        match *self {
+            Self::Density { capacity, penalty } => penalty * count as f32 / capacity as f32,
+            _ => 0.,
+        }
+    }
+
+    /// Fraction of its usual photosynthesis every cell produces with `count` cells alive.
+    pub fn light_share(&self, count: usize) -> f32 {
+        match *self {
+            Self::Carrying { capacity } if count > capacity => capacity as f32 / count as f32,
+            _ => 1.,
+        }
+    }
+
+    /// Number of cells to cull so `newborns` more fit next to the `count` alive.
+    pub fn overflow(&self, count: usize, newborns: usize) -> usize {
+        match *self {
+            Self::Cap { max } => (count + newborns).saturating_sub(max),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_population_control() {
+        let cap = PopulationControl::Cap { max: 10 };
+        assert_eq!(cap.overflow(5, 2), 0);
+        assert_eq!(cap.overflow(9, 2), 1);
+        assert_eq!(cap.upkeep(100), 0.);
+        assert_eq!(PopulationControl::Unlimited.overflow(usize::MAX - 2, 2), 0);
+
+        let density = PopulationControl::Density { capacity: 100, penalty: 0.5 };
+        assert_eq!(density.upkeep(200), 1.);
+        assert_eq!(density.light_share(200), 1.);
+
+        let carrying = PopulationControl::Carrying { capacity: 100 };
+        assert_eq!(carrying.light_share(50), 1.);
+        assert_eq!(carrying.light_share(400), 0.25);
+        assert_eq!(carrying.overflow(400, 2), 0);
+    }
+
+    #[test]
+    fn test_summary() {
+        let stats = PopulationStats { culled: 3, upkeep: 0.25, light_share: 0.5 };
+        assert_eq!(stats.summary(&PopulationControl::Unlimited), None);
+        assert_eq!(stats.summary(&PopulationControl::Cap { max: 10 }).as_deref(), Some("culled: 3"));
+        assert_eq!(stats.summary(&PopulationControl::Carrying { capacity: 10 }).as_deref(), Some("light share: 0.50"));
+    }
+}
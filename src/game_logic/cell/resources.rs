@@ -1,11 +1,74 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-#[derive(Resource, Deref, DerefMut)]
-pub struct FoodTimer(pub Timer);
+use crate::game_logic::config::SimulationConfig;
+use super::Genome;
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct DebugTimer(pub Timer);
 
+/// Number of fixed ticks simulated so far, carried over by snapshots.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct SimulationTick(pub u64);
+
+/// Hands out the innovation numbers of new brain connections, unique for the whole run.
+/// The same structural mutation happening in several cells during one tick gets the
+/// same numbers, so their descendants still line up in crossover.
+#[derive(Resource, Default)]
+pub struct Innovations {
+    next: u64,
+    /// Innovations of the incoming and outgoing connection of a neuron splitting the
+    /// connection with the key.
+    splits: HashMap<u64, (u64, u64)>,
+    connections: HashMap<(usize, usize), u64>,
+}
+impl Innovations {
+    pub fn fresh(&mut self) -> u64 {
+        self.next += 1;
+        self.next - 1
+    }
+
+    /// Innovations of the two connections replacing the split connection `innovation`.
+    pub fn split(&mut self, innovation: u64) -> (u64, u64) {
+        if let Some(split) = self.splits.get(&innovation) {
+            return *split;
+        }
+        let split = (self.fresh(), self.fresh());
+        self.splits.insert(innovation, split);
+        split
+    }
+
+    /// Innovation of a new connection between the neurons at `from` and `to`.
+    pub fn connection(&mut self, from: usize, to: usize) -> u64 {
+        if let Some(innovation) = self.connections.get(&(from, to)) {
+            return *innovation;
+        }
+        let innovation = self.fresh();
+        self.connections.insert((from, to), innovation);
+        innovation
+    }
+
+    /// Starts a new generation, mutations from now on get their own innovations.
+    pub fn forget(&mut self) {
+        self.splits.clear();
+        self.connections.clear();
+    }
+
+    /// Makes sure no innovation of a restored genome is handed out again.
+    pub fn restore(&mut self, genome: &Genome) {
+        let last = genome.connections.iter().map(|c| c.innovation).chain(genome.hidden.iter().copied()).max();
+        if let Some(last) = last {
+            self.next = self.next.max(last + 1);
+        }
+    }
+}
+
+/// Mates found this tick for cells that are about to split.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct Mates(pub HashMap<Entity, Entity>);
+
 #[derive(Resource)]
 pub struct TimeCounter(pub f32, pub f32);
 
@@ -31,4 +94,17 @@ impl DelayedDespawnQueue {
         self.current.clear();
         std::mem::swap(&mut self.current, &mut self.pending);
     }
-}
\ No newline at end of file
+}
+
+/// The only source of randomness of the simulation, seeded from [`SimulationConfig::seed`].
+#[derive(Resource, Deref, DerefMut)]
+pub struct SimRng(pub StdRng);
+impl FromWorld for SimRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.get_resource::<SimulationConfig>()
+            .and_then(|config| config.seed)
+            .unwrap_or_else(|| rand::thread_rng().gen());
+        info!("Simulation seed: {}", seed);
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::game_logic::config::SimulationConfig;
+use crate::game_logic::physics::{Velocity, AngularVelocity};
+use crate::game_logic::sprites::*;
+use super::*;
+
+/// Bumped whenever the layout of [`WorldSnapshot`] changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything needed to rebuild a running world. Inserted as a resource when
+/// resuming, [`snapshot_load`] spawns its content and removes it again.
+#[derive(Resource, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub version: u32,
+    /// The first tick simulated after resuming.
+    pub tick: u64,
+    pub cells: Vec<CellSnapshot>,
+    pub food: Vec<FoodSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CellSnapshot {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub velocity: Velocity,
+    pub angular_velocity: AngularVelocity,
+    pub energy: Energy,
+    pub genome: Genome,
+    /// The enabled connections with the weights learned so far.
+    pub connections: NeuronConnections,
+    pub state: NeuronState,
+    pub lineage: Lineage,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FoodSnapshot {
+    pub position: Vec3,
+    pub energy: f32,
+    /// Index of the food source that spawned it, if any.
+    pub origin: Option<usize>,
+    pub corpse: bool,
+}
+
+impl WorldSnapshot {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| SnapshotError::Io(path.to_path_buf(), e))?;
+        let snapshot: Self = ron::from_str(&text)
+            .map_err(|e| SnapshotError::Parse(path.to_path_buf(), e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot next to `path` first and then moves it in place,
+    /// so an interrupted save never destroys the previous snapshot.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let text = ron::to_string(self).map_err(SnapshotError::Serialize)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, text).map_err(|e| SnapshotError::Io(tmp_path.clone(), e))?;
+        fs::rename(&tmp_path, path).map_err(|e| SnapshotError::Io(path.to_path_buf(), e))
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Serialize(ron::Error),
+    Version(u32),
+}
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not access snapshot {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "could not parse snapshot {}: {}", path.display(), e),
+            Self::Serialize(e) => write!(f, "could not serialize snapshot: {}", e),
+            Self::Version(v) => write!(f, "unsupported snapshot version {}, expected {}", v, SNAPSHOT_VERSION),
+        }
+    }
+}
+impl std::error::Error for SnapshotError {}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct SnapshotTimer(pub Timer);
+
+pub fn snapshot_load(
+    mut commands: Commands,
+    mut cell_spawn_event_writer: EventWriter<CellSpawnEvent>,
+    mut flagellum_spawn_event_writer: EventWriter<FlagellumSpawnEvent>,
+    mut eye_spawn_event_writer: EventWriter<EyeSpawnEvent>,
+    mut food_spawn_event_writer: EventWriter<FoodSpawnEvent>,
+    food_sprite: Option<Res<FoodSprite>>,
+    light_sprite: Option<Res<LightSprite>>,
+    cell_sprite: Option<Res<CellSprite>>,
+    flagellum_sprite: Option<Res<FlagellumSprite>>,
+    eye_sprite: Option<Res<EyeSprite>>,
+    mut cell_count: ResMut<CellCount>,
+    mut lineages: ResMut<Lineages>,
+    mut innovations: ResMut<Innovations>,
+    mut tick: ResMut<SimulationTick>,
+    snapshot: Option<ResMut<WorldSnapshot>>,
+) {
+    let Some(mut snapshot) = snapshot else {
+        return;
+    };
+    commands.remove_resource::<WorldSnapshot>();
+    **tick = snapshot.tick;
+    info!("Loaded {} cells and {} food at tick {}", snapshot.cells.len(), snapshot.food.len(), snapshot.tick);
+
+    for cell in std::mem::take(&mut snapshot.cells) {
+        innovations.restore(&cell.genome);
+        let entity = spawn_cell(
+            &mut commands,
+            &mut cell_spawn_event_writer, &mut flagellum_spawn_event_writer, &mut eye_spawn_event_writer,
+            cell.position,
+            cell.rotation,
+            *cell.energy,
+            cell.genome,
+            Some(cell.state.0),
+            cell_sprite.as_deref(),
+            light_sprite.as_deref(),
+            flagellum_sprite.as_deref(),
+            eye_sprite.as_deref(),
+            cell_count.as_mut(),
+        );
+        lineages.restore(&cell.lineage);
+        commands.entity(entity).insert((cell.velocity, cell.angular_velocity, cell.connections, cell.lineage));
+    }
+    for food in std::mem::take(&mut snapshot.food) {
+        let entity = spawn_food(
+            &mut commands,
+            &mut food_spawn_event_writer,
+            food.position,
+            food.energy,
+            food_sprite.as_deref(),
+            light_sprite.as_deref(),
+        );
+        if let Some(origin) = food.origin {
+            commands.entity(entity).insert(FoodOrigin(origin));
+        }
+        if food.corpse {
+            commands.entity(entity).insert(Corpse);
+        }
+    }
+}
+
+pub fn snapshot_autosave(
+    mut timer: ResMut<SnapshotTimer>,
+    cell_query: Query<(
+        &Transform, &Velocity, &AngularVelocity,
+        &Energy, &Genome, &NeuronConnections, &NeuronState, &Lineage,
+        &Dead,
+    ), With<Cell>>,
+    food_query: Query<(&Transform, &Nutrient, Option<&FoodOrigin>, Has<Corpse>, &Dead), With<Food>>,
+    tick: Res<SimulationTick>,
+    config: Res<SimulationConfig>,
+) {
+    let Some(path) = &config.snapshot.path else {
+        return;
+    };
+    if config.snapshot.interval <= 0. {
+        return;
+    }
+    timer.tick(Duration::from_secs_f32(FIXED_DELTA));
+    if !timer.just_finished() {
+        return;
+    }
+
+    let snapshot = WorldSnapshot {
+        version: SNAPSHOT_VERSION,
+        // this tick is simulated already, advance_tick only runs after the save
+        tick: **tick + 1,
+        cells: cell_query.iter()
+            .filter(|(.., dead)| !***dead)
+            .map(|(
+                transform, velocity, angular_velocity,
+                energy, genome, connections, state, lineage, _
+            )| CellSnapshot {
+                position: transform.translation,
+                rotation: transform.rotation,
+                velocity: *velocity,
+                angular_velocity: *angular_velocity,
+                energy: *energy,
+                genome: genome.clone(),
+                connections: connections.clone(),
+                state: state.clone(),
+                lineage: *lineage,
+            })
+            .collect(),
+        food: food_query.iter()
+            .filter(|(.., dead)| !***dead)
+            .map(|(transform, nutrient, origin, corpse, _)| FoodSnapshot {
+                position: transform.translation,
+                energy: **nutrient,
+                origin: origin.map(|o| **o),
+                corpse,
+            })
+            .collect(),
+    };
+
+    match snapshot.save(path) {
+        Ok(()) => info!("Saved snapshot with {} cells to {}", snapshot.cells.len(), path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use ndarray::Array1;
+    use crate::game_logic::config::SnapshotConfig;
+
+    #[test]
+    fn test_roundtrip() {
+        let snapshot = WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            tick: 600,
+            cells: vec![CellSnapshot {
+                position: Vec3::new(1., 2., 0.),
+                rotation: Quat::from_rotation_z(0.5),
+                velocity: Velocity(Vec2::new(3., -4.)),
+                angular_velocity: AngularVelocity(0.25),
+                energy: Energy(100.),
+                genome: Genome {
+                    split_energy: 200.,
+                    split_ratio: 0.4,
+                    chloroplasts: 2,
+                    mouth: 0.5,
+                    think_interval: 0.1,
+                    flagella: vec![(0.5, -0.5)],
+                    eyes: vec![EyeGene { position: 3., fov: 0.5, range: 400. }],
+                    connections: vec![
+                        Connection::new(3, 0, 2, 0.5),
+                        Connection { plasticity: 0.1, enabled: false, ..Connection::new(8, 2, 2, -1.) },
+                    ],
+                    hidden: vec![5],
+                    biases: Array1::from_vec(vec![0.1, 0.2, 0.3]),
+                    time_constants: Array1::from_vec(vec![0.05, 0.5, 2.]),
+                },
+                connections: NeuronConnections(vec![Connection::new(3, 0, 2, 0.7)]),
+                state: NeuronState(Array1::zeros(3)),
+                lineage: Lineage { id: 4, parent: Some(1), generation: 2, birth_tick: 540 },
+            }],
+            food: vec![FoodSnapshot { position: Vec3::new(-5., 5., 0.), energy: 10., origin: Some(1), corpse: false }],
+        };
+
+        let text = ron::to_string(&snapshot).unwrap();
+        let loaded: WorldSnapshot = ron::from_str(&text).unwrap();
+        let (a, b) = (&snapshot.cells[0], &loaded.cells[0]);
+        assert_eq!(a.position, b.position);
+        assert_eq!(a.rotation, b.rotation);
+        assert_eq!(*a.velocity, *b.velocity);
+        assert_eq!(a.genome.connections, b.genome.connections);
+        assert_eq!(a.genome.hidden, b.genome.hidden);
+        assert_eq!(*a.connections, *b.connections);
+        assert_eq!(a.genome.biases, b.genome.biases);
+        assert_eq!(a.genome.flagella, b.genome.flagella);
+        assert_eq!(a.lineage, b.lineage);
+        assert_eq!(loaded.tick, snapshot.tick);
+        assert_eq!(loaded.food[0].position, snapshot.food[0].position);
+        assert_eq!(loaded.food[0].origin, snapshot.food[0].origin);
+    }
+
+    #[test]
+    fn test_resume_after_saved_tick() {
+        let path = std::env::temp_dir().join(format!("snapshot-test-{}.ron", std::process::id()));
+        let mut world = World::new();
+        world.insert_resource(SimulationConfig {
+            snapshot: SnapshotConfig { path: Some(path.clone()), interval: FIXED_DELTA, ..default() },
+            ..default()
+        });
+        world.insert_resource(SnapshotTimer(Timer::from_seconds(FIXED_DELTA, TimerMode::Repeating)));
+        world.insert_resource(SimulationTick(41));
+        world.run_system_once(snapshot_autosave);
+        let snapshot = WorldSnapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mut world = World::new();
+        world.init_resource::<Events<CellSpawnEvent>>();
+        world.init_resource::<Events<FlagellumSpawnEvent>>();
+        world.init_resource::<Events<EyeSpawnEvent>>();
+        world.init_resource::<Events<FoodSpawnEvent>>();
+        world.init_resource::<CellCount>();
+        world.init_resource::<Lineages>();
+        world.init_resource::<Innovations>();
+        world.init_resource::<SimulationTick>();
+        world.insert_resource(snapshot.unwrap());
+        world.run_system_once(snapshot_load);
+        // tick 41 was simulated before the save, the resumed run goes on with the next one
+        assert_eq!(**world.resource::<SimulationTick>(), 42);
+    }
+}
@@ -1,11 +1,19 @@
 use bevy::{prelude::*, sprite::Anchor};
 use bevy_prototype_lyon::prelude::*;
 use bevy_rapier2d::prelude::*;
-use ndarray::{Array2, Array1};
+use ndarray::Array1;
 
 use crate::game_logic::sprites::*;
 use super::*;
 
+pub const FOOD_RADIUS: f32 = 10.;
+
+#[inline]
+pub fn cell_radius(energy: f32) -> f32 {
+    5. * energy.sqrt()
+}
+
+/// Spawns a cell with its organs, a cell without a `state` starts with all its neurons at rest.
 pub fn spawn_cell(
     commands: &mut Commands,
     cell_spawn_event_writer: &mut EventWriter<CellSpawnEvent>,
@@ -14,13 +22,8 @@ pub fn spawn_cell(
     position: Vec3,
     rotation: Quat,
     energy: f32,
-    split_energy: f32,
-    chloroplasts: u8,
-    flagella_params: Vec<(f32, f32)>,
-    eye_params: Vec<f32>,
-    weights: Array2<f32>,
-    biases: Array1<f32>,
-    state: Array1<f32>,
+    genome: Genome,
+    state: Option<Array1<f32>>,
     cell_sprite: Option<&CellSprite>,
     light_sprite: Option<&LightSprite>,
     flagellum_sprite: Option<&FlagellumSprite>,
@@ -29,16 +32,14 @@ pub fn spawn_cell(
 ) -> Entity {
     **cell_count += 1;
 
-    let radius = 5. * energy.sqrt();
-
-    let fov = f32::to_radians(30.);
-    let range = 1000.;
+    let radius = cell_radius(energy);
+    let state = state.unwrap_or_else(|| Array1::zeros(genome.neuron_count()));
 
-    let flagella: Vec<Entity> = flagella_params.iter().map(
+    let flagella: Vec<Entity> = genome.flagella.iter().map(
         |(pos, ang)| spawn_flagellum(commands, flagellum_spawn_event_writer, *pos, *ang, radius, flagellum_sprite)
     ).collect();
-    let eyes: Vec<Entity> = eye_params.iter().map(
-        |pos| spawn_eye(commands, eye_spawn_event_writer, *pos, radius, fov, range, eye_sprite)
+    let eyes: Vec<Entity> = genome.eyes.iter().map(
+        |eye| spawn_eye(commands, eye_spawn_event_writer, eye.position, radius, eye.fov, eye.range, eye_sprite)
     ).collect(); 
     let collider = commands.spawn((
         CellColliderTag,
@@ -68,7 +69,7 @@ pub fn spawn_cell(
                 },
                 transform: Transform::from_translation(Vec3::new(0.,0.,-100.)),
                 ..default()
-            }).id());
+            }).insert(LightGlow).id());
         }
         vec
     };
@@ -79,10 +80,9 @@ pub fn spawn_cell(
             eyes.clone(),
             collider,
             sprites.clone(),
-            flagella_params,
-            eye_params,
-            energy, split_energy, chloroplasts,
-            weights, biases, state,
+            genome,
+            energy,
+            state,
             position, rotation,
         ),
     )).id();
@@ -216,19 +216,20 @@ pub fn spawn_food(
     commands: &mut Commands,
     food_spawn_event_writer: &mut EventWriter<FoodSpawnEvent>,
     position: Vec3,
+    energy: f32,
     food_sprite: Option<&FoodSprite>,
     light_sprite: Option<&LightSprite>,
 ) -> Entity {
     let food = commands.spawn((
-        FoodBundle::new(),
+        FoodBundle::new(energy),
         SpatialBundle::from_transform(Transform::from_translation(position)),
-        Collider::ball(10.),
+        Collider::ball(FOOD_RADIUS),
     )).with_children(|c| {
         if let Some(sprite) = food_sprite {
             c.spawn(SpriteBundle{
                 texture: sprite.0.clone(),
                 sprite: Sprite{
-                    custom_size: Some(Vec2::new(20., 20.)),
+                    custom_size: Some(Vec2::new(FOOD_RADIUS * 2., FOOD_RADIUS * 2.)),
                     ..default()
                 },
                 ..default()
@@ -259,4 +260,4 @@ pub fn despawn_food(
 ) {
     despawn_queue.add(food_entity);
     food_despawn_event_writer.send(FoodDespawnEvent(food_entity));
-}
\ No newline at end of file
+}
@@ -0,0 +1,821 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Serialize, Deserialize};
+
+use super::cell::{cell_radius, FIXED_DELTA, LineageError, Lineages, SnapshotError, WorldSnapshot};
+use super::obstacle::{MapError, ObstacleMap};
+
+/// Tuning knobs of the simulation. Every field has a default, so a config file
+/// only needs to list the values it wants to change.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SimulationConfig {
+    pub mutation: MutationConfig,
+    pub brain: BrainModel,
+    pub plasticity: PlasticityConfig,
+    pub energy_penalty: f32,
+    /// Energy per second a chloroplast produces in a light of 1.
+    pub chloroplast_production: f32,
+    pub light: LightConfig,
+    pub metabolism: MetabolismConfig,
+    /// Mass every chloroplast adds to its cell, making photosynthesising cells sluggish.
+    pub chloroplast_mass: f32,
+    pub intercell_push: f32,
+    pub obstacle_push: f32,
+    pub population: PopulationControl,
+    pub drag: f32,
+    pub angular_drag: f32,
+    pub world: WorldConfig,
+    /// RON file with the obstacles of the world.
+    pub map: Option<PathBuf>,
+    pub player_speed: f32,
+    pub player_angle_speed: f32,
+    /// Seed of the simulation RNG, a random one is picked and logged when unset.
+    pub seed: Option<u64>,
+    /// Runs the simulation single-threaded, one fixed tick per frame and with ordered
+    /// iteration, so that a seed and a tick count always produce the same world.
+    pub deterministic: bool,
+    pub reproduction: ReproductionConfig,
+    pub food: Vec<FoodSource>,
+    pub predation: PredationConfig,
+    pub corpse: CorpseConfig,
+    pub snapshot: SnapshotConfig,
+    pub lineage: LineageConfig,
+}
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            mutation: MutationConfig::default(),
+            brain: BrainModel::Discrete,
+            plasticity: PlasticityConfig::default(),
+            energy_penalty: 0.01,
+            chloroplast_production: 1.,
+            light: LightConfig::default(),
+            metabolism: MetabolismConfig::default(),
+            chloroplast_mass: 0.05,
+            intercell_push: 1.,
+            obstacle_push: 10.,
+            population: PopulationControl::Cap { max: 2000 },
+            drag: 2.,
+            angular_drag: 2.,
+            world: WorldConfig::default(),
+            map: None,
+            player_speed: 500.,
+            player_angle_speed: 7.,
+            seed: None,
+            deterministic: false,
+            reproduction: ReproductionConfig::default(),
+            food: vec![FoodSource::default()],
+            predation: PredationConfig::default(),
+            corpse: CorpseConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            lineage: LineageConfig::default(),
+        }
+    }
+}
+
+/// Energy per second every part of a cell costs, on top of [`SimulationConfig::energy_penalty`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MetabolismConfig {
+    /// Per flagellum at full activation, weaker strokes cost proportionally less.
+    pub flagellum: f32,
+    pub eye: f32,
+    /// For every square unit an eye senses.
+    pub vision: f32,
+    pub neuron: f32,
+    /// Per enabled connection.
+    pub connection: f32,
+    /// Per brain update, for every neuron and enabled connection it goes through.
+    pub thinking: f32,
+    /// For a mouth of full strength, weaker mouths cost proportionally less.
+    pub mouth: f32,
+}
+impl Default for MetabolismConfig {
+    fn default() -> Self {
+        Self {
+            flagellum: 0.5,
+            eye: 0.05,
+            vision: 5e-7,
+            neuron: 0.005,
+            connection: 0.001,
+            thinking: 2e-5,
+            mouth: 1.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub shape: WorldShape,
+    /// Fraction of the speed towards a wall a cell keeps after bouncing off it.
+    pub wall_bounce: f32,
+}
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            shape: WorldShape::Unbounded,
+            wall_bounce: 0.5,
+        }
+    }
+}
+
+/// Extent of the world, always centered on the origin.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum WorldShape {
+    Unbounded,
+    /// Walls along the edges of a rectangle.
+    Rect { half_size: Vec2 },
+    /// A round wall.
+    Circle { radius: f32 },
+    /// A rectangle whose opposite edges are glued together.
+    Torus { half_size: Vec2 },
+}
+
+/// How the number of cells is kept in check.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PopulationControl {
+    /// Only food and metabolism hold the population back.
+    Unlimited,
+    /// Random cells are culled to make room for newborns once `max` cells are alive.
+    Cap { max: usize },
+    /// Every cell loses `penalty` energy per second for each `capacity` cells alive.
+    Density { capacity: usize, penalty: f32 },
+    /// Photosynthesis is shared once more than `capacity` cells are alive,
+    /// every cell then produces `capacity / count` of its usual amount.
+    Carrying { capacity: usize },
+}
+
+/// How cells turn what they sense into flagella activations.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BrainModel {
+    /// All neurons update at once, every time the thinking timer finishes.
+    Discrete,
+    /// Neurons follow their inputs every tick, each at the speed of its heritable time constant.
+    Ctrnn,
+}
+
+/// How connection weights change during a cell's life, at the heritable rate of every connection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PlasticityConfig {
+    pub rule: PlasticityRule,
+    /// Daughters inherit the weights their mother learned instead of the ones she was born with.
+    pub inherit_learned: bool,
+    /// Learning never pushes a weight beyond this magnitude.
+    pub max_weight: f32,
+}
+impl Default for PlasticityConfig {
+    fn default() -> Self {
+        Self {
+            rule: PlasticityRule::Off,
+            inherit_learned: false,
+            max_weight: 5.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PlasticityRule {
+    Off,
+    /// Connections between neurons active together strengthen.
+    Hebbian,
+    /// Hebbian learning scaled by the energy gained since the last brain update,
+    /// so whatever preceded a loss is unlearned.
+    Modulated,
+}
+
+/// Standard deviations of the noise added to a genome on division.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MutationConfig {
+    /// Organ positions and angles.
+    pub rate: f32,
+    /// Neuron weights and biases.
+    pub weight_rate: f32,
+    pub split_energy_rate: f32,
+    pub min_split_energy: f32,
+    pub split_ratio_rate: f32,
+    /// Chance per division of gaining or losing a chloroplast.
+    pub chloroplast_rate: f64,
+    pub mouth_rate: f32,
+    /// Eye field of view, in radians.
+    pub eye_fov_rate: f32,
+    /// Eye range, relative to the current one.
+    pub eye_range_rate: f32,
+    /// Neuron time constants, relative to the current ones.
+    pub time_constant_rate: f32,
+    /// Time between brain updates, relative to the current one.
+    pub think_interval_rate: f32,
+    /// Connection learning rates.
+    pub plasticity_rate: f32,
+    pub structure: StructuralMutationConfig,
+}
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self {
+            rate: 0.01,
+            weight_rate: 0.1,
+            split_energy_rate: 0.1,
+            min_split_energy: 10.,
+            split_ratio_rate: 0.01,
+            chloroplast_rate: 0.05,
+            mouth_rate: 0.02,
+            eye_fov_rate: 0.02,
+            eye_range_rate: 0.05,
+            time_constant_rate: 0.1,
+            think_interval_rate: 0.05,
+            plasticity_rate: 0.01,
+            structure: StructuralMutationConfig::default(),
+        }
+    }
+}
+
+/// Chances per division of adding or removing an organ, a hidden neuron or a connection.
+/// New hidden neurons are put in the middle of an existing connection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StructuralMutationConfig {
+    pub add_flagellum: f64,
+    pub remove_flagellum: f64,
+    pub add_eye: f64,
+    pub remove_eye: f64,
+    pub add_neuron: f64,
+    pub remove_neuron: f64,
+    pub add_connection: f64,
+}
+impl Default for StructuralMutationConfig {
+    fn default() -> Self {
+        Self {
+            add_flagellum: 0.02,
+            remove_flagellum: 0.01,
+            add_eye: 0.02,
+            remove_eye: 0.01,
+            add_neuron: 0.02,
+            remove_neuron: 0.01,
+            add_connection: 0.05,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ReproductionConfig {
+    /// A cell ready to split mixes its genome with a touching cell, when there is one.
+    pub sexual: bool,
+    /// Cells with enough energy only split once their brain fires the divide neuron.
+    pub voluntary: bool,
+    /// Largest genetic distance at which two cells can still mate, unlimited when unset.
+    pub max_mate_distance: Option<f32>,
+    pub division: DivisionConfig,
+}
+
+/// Where the daughters of a dividing cell are placed. They are lined up along the
+/// parent's axis, around its centre, and start with its velocity.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DivisionConfig {
+    /// Space left between the daughters' edges.
+    pub gap: f32,
+    /// Angle in radians the daughters are turned away from the parent's heading, in opposite directions.
+    pub turn: f32,
+    /// Speed at which the daughters move apart, on top of the parent's velocity.
+    pub separation_speed: f32,
+}
+impl Default for DivisionConfig {
+    fn default() -> Self {
+        Self {
+            gap: 1.,
+            turn: 0.1,
+            separation_speed: 0.,
+        }
+    }
+}
+impl DivisionConfig {
+    /// Offsets along the parent's axis and separation speeds of two daughters with
+    /// `energies`. Both are weighted so the centre and momentum of the parent are kept.
+    pub fn layout(&self, energies: [f32; 2]) -> [(f32, f32); 2] {
+        let distance = cell_radius(energies[0]) + cell_radius(energies[1]) + self.gap;
+        let total = energies[0] + energies[1];
+        [energies[1] / total, -energies[0] / total]
+            .map(|weight| (weight * distance, weight * self.separation_speed))
+    }
+}
+
+/// How cells with a mouth feed on smaller cells they touch.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PredationConfig {
+    /// Energy per second drained by a mouth of full strength.
+    pub drain_rate: f32,
+    /// Fraction of the taken energy the predator gains, the rest is lost.
+    pub efficiency: f32,
+    /// Prey has to be at most this many times the predator's radius to be attacked.
+    pub max_size_ratio: f32,
+    /// Prey at most this many times the predator's radius is swallowed whole.
+    pub engulf_size_ratio: f32,
+}
+impl Default for PredationConfig {
+    fn default() -> Self {
+        Self {
+            drain_rate: 20.,
+            efficiency: 0.5,
+            max_size_ratio: 0.9,
+            engulf_size_ratio: 0.5,
+        }
+    }
+}
+impl PredationConfig {
+    /// Whether a cell of `radius` is big enough to attack one of `prey_radius`.
+    pub fn can_attack(&self, radius: f32, prey_radius: f32) -> bool {
+        prey_radius <= radius * self.max_size_ratio
+    }
+
+    /// Energy a mouth of strength `mouth` takes from its prey in one tick, and whether
+    /// the prey is swallowed whole.
+    pub fn bite(&self, mouth: f32, radius: f32, prey_radius: f32, prey_energy: f32) -> (f32, bool) {
+        let engulfed = prey_radius <= radius * self.engulf_size_ratio;
+        let taken = match engulfed {
+            true => prey_energy,
+            false => (mouth * self.drain_rate * FIXED_DELTA).min(prey_energy),
+        };
+        (taken, engulfed)
+    }
+}
+
+/// Remains of starved cells, eaten like food.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CorpseConfig {
+    /// Fraction of the energy left in a starved cell that ends up in its corpse, 0 disables corpses.
+    pub fraction: f32,
+    /// Simulated seconds after which a corpse holds half of its energy.
+    pub half_life: f32,
+    /// Corpses holding less energy than this are gone.
+    pub min_energy: f32,
+}
+impl Default for CorpseConfig {
+    fn default() -> Self {
+        Self {
+            fraction: 0.5,
+            half_life: 30.,
+            min_energy: 1.,
+        }
+    }
+}
+
+/// Brightness of the world, which drives photosynthesis. The light at a point is
+/// the ambient light plus every source, never negative, dimmed by the night.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LightConfig {
+    pub ambient: f32,
+    pub sources: Vec<LightSource>,
+    /// Constant light when unset.
+    pub day: Option<DayCycle>,
+}
+impl Default for LightConfig {
+    fn default() -> Self {
+        Self {
+            ambient: 1.,
+            sources: Vec::new(),
+            day: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LightSource {
+    /// Light growing by `slope` per unit of distance, zero at the origin.
+    Gradient { slope: Vec2 },
+    /// A Gaussian patch of light, `intensity` bright at its center. Negative intensities cast a shadow.
+    Spot { center: Vec2, sigma: f32, intensity: f32 },
+    /// Smooth random patches about `scale` wide, between 0 and `intensity`.
+    Noise { seed: u64, scale: f32, intensity: f32 },
+}
+
+/// The world starts at noon and is darkest half a `period` later.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct DayCycle {
+    /// Length of a day in seconds.
+    pub period: f32,
+    /// Fraction of the light left at midnight.
+    pub night: f32,
+}
+
+/// A place where food keeps appearing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FoodSource {
+    pub shape: FoodShape,
+    /// Food items spawned per simulated second.
+    pub rate: f32,
+    /// Most items of this source lying around at once.
+    pub cap: usize,
+    /// Energy a cell gains by eating one item.
+    pub energy: f32,
+}
+impl Default for FoodSource {
+    fn default() -> Self {
+        Self {
+            shape: FoodShape::Gaussian { center: Vec2::ZERO, sigma: 15000. },
+            rate: 20.,
+            cap: 20000,
+            energy: 10.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum FoodShape {
+    /// Anywhere inside the rectangle.
+    Uniform { min: Vec2, max: Vec2 },
+    /// Normally distributed around a fixed center.
+    Gaussian { center: Vec2, sigma: f32 },
+    /// A Gaussian patch whose center circles `center` once every `period` seconds.
+    Moving { center: Vec2, sigma: f32, orbit: f32, period: f32 },
+    /// A Gaussian patch that only spawns for the first `duration` seconds of every `period`.
+    Bloom { center: Vec2, sigma: f32, period: f32, duration: f32 },
+}
+impl FoodShape {
+    /// Whether the source spawns anything at simulated time `time`.
+    pub fn active(&self, time: f32) -> bool {
+        match self {
+            Self::Bloom { period, duration, .. } => time.rem_euclid(*period) < *duration,
+            _ => true,
+        }
+    }
+
+    pub fn sample(&self, time: f32, rng: &mut impl Rng) -> Vec2 {
+        let gaussian = |center: Vec2, sigma: f32, rng: &mut _| {
+            let normal = Normal::new(0., sigma).unwrap();
+            center + Vec2::new(normal.sample(rng), normal.sample(rng))
+        };
+        match self {
+            Self::Uniform { min, max } => Vec2::new(
+                rng.gen_range(min.x..=max.x),
+                rng.gen_range(min.y..=max.y),
+            ),
+            Self::Gaussian { center, sigma } => gaussian(*center, *sigma, rng),
+            Self::Moving { center, sigma, orbit, period } => {
+                let angle = 2. * PI * time / period;
+                gaussian(*center + *orbit * Vec2::new(angle.cos(), angle.sin()), *sigma, rng)
+            },
+            Self::Bloom { center, sigma, .. } => gaussian(*center, *sigma, rng),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Where autosaved snapshots are written.
+    pub path: Option<PathBuf>,
+    /// Simulated seconds between autosaves, 0 disables autosaving.
+    pub interval: f32,
+    /// Snapshot to resume from instead of spawning the founder cell.
+    pub load: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LineageConfig {
+    /// Append-only log of births and deaths, one record per line.
+    pub log_path: Option<PathBuf>,
+    /// Where the phylogenetic tree is exported in Newick format.
+    pub newick_path: Option<PathBuf>,
+    /// Simulated seconds between tree exports, 0 disables exporting.
+    pub export_interval: f32,
+}
+
+impl SimulationConfig {
+    /// Builds the config from the command line: `[config.ron] [--seed <u64>] [--load <snapshot>]`.
+    /// Without a config file the defaults are used, the flags override the file.
+    pub fn from_args() -> Result<Self, ConfigError> {
+        let mut path = None;
+        let mut seed = None;
+        let mut load = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or(ConfigError::Args("--seed needs a value".to_string()))?;
+                    seed = Some(value.parse::<u64>().map_err(|e| ConfigError::Args(format!("invalid seed `{}`: {}", value, e)))?);
+                },
+                "--load" => {
+                    load = Some(PathBuf::from(args.next().ok_or(ConfigError::Args("--load needs a value".to_string()))?));
+                },
+                _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+                _ => return Err(ConfigError::Args(format!("unexpected argument `{}`", arg))),
+            }
+        }
+
+        let mut config = match path {
+            Some(path) => Self::load(path)?,
+            None => Self::default(),
+        };
+        if seed.is_some() {
+            config.seed = seed;
+        }
+        if load.is_some() {
+            config.snapshot.load = load;
+        }
+        Ok(config)
+    }
+
+    /// Reads the obstacle map, an empty one when the config names none.
+    pub fn load_map(&self) -> Result<ObstacleMap, ConfigError> {
+        self.map.as_ref()
+            .map_or(Ok(ObstacleMap::default()), ObstacleMap::load)
+            .map_err(ConfigError::Map)
+    }
+
+    /// Reads the snapshot to resume from, if the config names one.
+    pub fn load_snapshot(&self) -> Result<Option<WorldSnapshot>, ConfigError> {
+        self.snapshot.load.as_ref()
+            .map(WorldSnapshot::load)
+            .transpose()
+            .map_err(ConfigError::Snapshot)
+    }
+
+    /// Reads back the lineage log when resuming from a snapshot at `resume_tick`.
+    /// A fresh run refuses to start over the log of an earlier one.
+    pub fn load_lineages(&self, resume_tick: Option<u64>) -> Result<Lineages, ConfigError> {
+        let Some(path) = self.lineage.log_path.as_ref().filter(|path| path.exists()) else {
+            return Ok(Lineages::new());
+        };
+        match resume_tick {
+            Some(tick) => Lineages::load(path, tick),
+            None => Err(LineageError::Exists(path.clone())),
+        }.map_err(ConfigError::Lineage)
+    }
+
+    /// Reads and validates a RON config file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let config: Self = ron::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // drag is applied as a (1 - drag * dt) multiplier, which has to stay non-negative
+        let max_drag = 1. / FIXED_DELTA;
+
+        check("mutation.rate", self.mutation.rate, self.mutation.rate >= 0., ">= 0")?;
+        check("mutation.weight_rate", self.mutation.weight_rate, self.mutation.weight_rate >= 0., ">= 0")?;
+        check("mutation.split_energy_rate", self.mutation.split_energy_rate, self.mutation.split_energy_rate >= 0., ">= 0")?;
+        check("mutation.min_split_energy", self.mutation.min_split_energy, self.mutation.min_split_energy > 0., "> 0")?;
+        check("mutation.split_ratio_rate", self.mutation.split_ratio_rate, self.mutation.split_ratio_rate >= 0., ">= 0")?;
+        check("mutation.chloroplast_rate", self.mutation.chloroplast_rate, (0. ..=1.).contains(&self.mutation.chloroplast_rate), "in [0, 1]")?;
+        let structure = &self.mutation.structure;
+        for (field, chance) in [
+            ("mutation.structure.add_flagellum", structure.add_flagellum),
+            ("mutation.structure.remove_flagellum", structure.remove_flagellum),
+            ("mutation.structure.add_eye", structure.add_eye),
+            ("mutation.structure.remove_eye", structure.remove_eye),
+            ("mutation.structure.add_neuron", structure.add_neuron),
+            ("mutation.structure.remove_neuron", structure.remove_neuron),
+            ("mutation.structure.add_connection", structure.add_connection),
+        ] {
+            check(field, chance, (0. ..=1.).contains(&chance), "in [0, 1]")?;
+        }
+        match self.world.shape {
+            WorldShape::Unbounded => (),
+            WorldShape::Rect { half_size } | WorldShape::Torus { half_size } =>
+                check("world.shape.half_size", half_size, half_size.cmpgt(Vec2::ZERO).all(), "> 0")?,
+            WorldShape::Circle { radius } => check("world.shape.radius", radius, radius > 0., "> 0")?,
+        }
+        check("world.wall_bounce", self.world.wall_bounce, (0. ..=1.).contains(&self.world.wall_bounce), "in [0, 1]")?;
+        check("energy_penalty", self.energy_penalty, (0. ..max_drag).contains(&self.energy_penalty), "in [0, 60)")?;
+        check("chloroplast_production", self.chloroplast_production, self.chloroplast_production >= 0., ">= 0")?;
+        check("light.ambient", self.light.ambient, self.light.ambient.is_finite(), "finite")?;
+        for source in &self.light.sources {
+            match *source {
+                LightSource::Gradient { slope } => check("light.sources.slope", slope, slope.is_finite(), "finite")?,
+                LightSource::Spot { sigma, intensity, .. } => {
+                    check("light.sources.sigma", sigma, sigma > 0., "> 0")?;
+                    check("light.sources.intensity", intensity, intensity.is_finite(), "finite")?;
+                },
+                LightSource::Noise { scale, intensity, .. } => {
+                    check("light.sources.scale", scale, scale > 0., "> 0")?;
+                    check("light.sources.intensity", intensity, intensity.is_finite(), "finite")?;
+                },
+            }
+        }
+        if let Some(day) = self.light.day {
+            check("light.day.period", day.period, day.period > 0., "> 0")?;
+            check("light.day.night", day.night, (0. ..=1.).contains(&day.night), "in [0, 1]")?;
+        }
+        check("chloroplast_mass", self.chloroplast_mass, self.chloroplast_mass >= 0., ">= 0")?;
+        check("intercell_push", self.intercell_push, self.intercell_push >= 0., ">= 0")?;
+        check("obstacle_push", self.obstacle_push, self.obstacle_push >= 0., ">= 0")?;
+        match self.population {
+            PopulationControl::Unlimited => (),
+            PopulationControl::Cap { max } => check("population.max", max, max > 0, "> 0")?,
+            PopulationControl::Density { capacity, penalty } => {
+                check("population.capacity", capacity, capacity > 0, "> 0")?;
+                check("population.penalty", penalty, penalty >= 0., ">= 0")?;
+            },
+            PopulationControl::Carrying { capacity } => check("population.capacity", capacity, capacity > 0, "> 0")?,
+        }
+        check("drag", self.drag, (0. ..=max_drag).contains(&self.drag), "in [0, 60]")?;
+        check("angular_drag", self.angular_drag, (0. ..=max_drag).contains(&self.angular_drag), "in [0, 60]")?;
+        check("player_speed", self.player_speed, self.player_speed >= 0., ">= 0")?;
+        check("player_angle_speed", self.player_angle_speed, self.player_angle_speed >= 0., ">= 0")?;
+        if let Some(distance) = self.reproduction.max_mate_distance {
+            check("reproduction.max_mate_distance", distance, distance >= 0., ">= 0")?;
+        }
+        let division = &self.reproduction.division;
+        check("reproduction.division.gap", division.gap, division.gap >= 0., ">= 0")?;
+        check("reproduction.division.turn", division.turn, division.turn.abs() <= PI, "in [-pi, pi]")?;
+        check("reproduction.division.separation_speed", division.separation_speed, division.separation_speed >= 0., ">= 0")?;
+        check("mutation.mouth_rate", self.mutation.mouth_rate, self.mutation.mouth_rate >= 0., ">= 0")?;
+        check("mutation.eye_fov_rate", self.mutation.eye_fov_rate, self.mutation.eye_fov_rate >= 0., ">= 0")?;
+        check("mutation.eye_range_rate", self.mutation.eye_range_rate, self.mutation.eye_range_rate >= 0., ">= 0")?;
+        check("mutation.time_constant_rate", self.mutation.time_constant_rate, self.mutation.time_constant_rate >= 0., ">= 0")?;
+        check("mutation.think_interval_rate", self.mutation.think_interval_rate, self.mutation.think_interval_rate >= 0., ">= 0")?;
+        check("mutation.plasticity_rate", self.mutation.plasticity_rate, self.mutation.plasticity_rate >= 0., ">= 0")?;
+        check("plasticity.max_weight", self.plasticity.max_weight, self.plasticity.max_weight > 0., "> 0")?;
+        let metabolism = &self.metabolism;
+        for (field, cost) in [
+            ("metabolism.flagellum", metabolism.flagellum),
+            ("metabolism.eye", metabolism.eye),
+            ("metabolism.vision", metabolism.vision),
+            ("metabolism.neuron", metabolism.neuron),
+            ("metabolism.connection", metabolism.connection),
+            ("metabolism.thinking", metabolism.thinking),
+            ("metabolism.mouth", metabolism.mouth),
+        ] {
+            check(field, cost, cost >= 0., ">= 0")?;
+        }
+        let predation = &self.predation;
+        check("predation.drain_rate", predation.drain_rate, predation.drain_rate >= 0., ">= 0")?;
+        check("predation.efficiency", predation.efficiency, (0. ..=1.).contains(&predation.efficiency), "in [0, 1]")?;
+        check("predation.max_size_ratio", predation.max_size_ratio, predation.max_size_ratio >= 0., ">= 0")?;
+        check("predation.engulf_size_ratio", predation.engulf_size_ratio, (0. ..=predation.max_size_ratio).contains(&predation.engulf_size_ratio), "in [0, max_size_ratio]")?;
+        check("corpse.fraction", self.corpse.fraction, (0. ..=1.).contains(&self.corpse.fraction), "in [0, 1]")?;
+        check("corpse.half_life", self.corpse.half_life, self.corpse.half_life > 0., "> 0")?;
+        check("corpse.min_energy", self.corpse.min_energy, self.corpse.min_energy >= 0., ">= 0")?;
+        for source in &self.food {
+            check("food.rate", source.rate, source.rate >= 0., ">= 0")?;
+            check("food.energy", source.energy, source.energy >= 0., ">= 0")?;
+            match source.shape {
+                FoodShape::Uniform { min, max } => check("food.shape.max", max, max.cmpge(min).all(), ">= min")?,
+                FoodShape::Gaussian { sigma, .. } => check("food.shape.sigma", sigma, sigma >= 0., ">= 0")?,
+                FoodShape::Moving { sigma, period, .. } => {
+                    check("food.shape.sigma", sigma, sigma >= 0., ">= 0")?;
+                    check("food.shape.period", period, period > 0., "> 0")?;
+                },
+                FoodShape::Bloom { sigma, period, duration, .. } => {
+                    check("food.shape.sigma", sigma, sigma >= 0., ">= 0")?;
+                    check("food.shape.period", period, period > 0., "> 0")?;
+                    check("food.shape.duration", duration, (0. ..=period).contains(&duration), "in [0, period]")?;
+                },
+            }
+        }
+        check("snapshot.interval", self.snapshot.interval, self.snapshot.interval >= 0., ">= 0")?;
+        check("lineage.export_interval", self.lineage.export_interval, self.lineage.export_interval >= 0., ">= 0")?;
+        Ok(())
+    }
+}
+
+fn check(field: &'static str, value: impl fmt::Display, ok: bool, expected: &'static str) -> Result<(), ConfigError> {
+    match ok {
+        true => Ok(()),
+        false => Err(ConfigError::OutOfRange { field, value: value.to_string(), expected }),
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Args(String),
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    OutOfRange {
+        field: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    Map(MapError),
+    Snapshot(SnapshotError),
+    Lineage(LineageError),
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Args(e) => write!(f, "{}", e),
+            Self::Io(path, e) => write!(f, "could not read config {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "could not parse config {}: {}", path.display(), e),
+            Self::OutOfRange { field, value, expected } => write!(f, "config value `{}` = {} out of range, expected {}", field, value, expected),
+            Self::Map(e) => write!(f, "{}", e),
+            Self::Snapshot(e) => write!(f, "{}", e),
+            Self::Lineage(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert!(SimulationConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_partial_file() {
+        let config: SimulationConfig = ron::from_str("(mutation: (rate: 0.5), population: Cap(max: 10))").unwrap();
+        assert_eq!(config.mutation.rate, 0.5);
+        assert_eq!(config.mutation.weight_rate, MutationConfig::default().weight_rate);
+        assert_eq!(config.population, PopulationControl::Cap { max: 10 });
+        assert_eq!(config.drag, SimulationConfig::default().drag);
+    }
+
+    #[test]
+    fn test_missing_files() {
+        assert!(SimulationConfig::default().load_map().is_ok_and(|map| map.obstacles.is_empty()));
+        let config = SimulationConfig { map: Some("missing.ron".into()), ..default() };
+        assert!(matches!(config.load_map(), Err(ConfigError::Map(_))));
+        let config = SimulationConfig { snapshot: SnapshotConfig { load: Some("missing.ron".into()), ..default() }, ..default() };
+        assert!(matches!(config.load_snapshot(), Err(ConfigError::Snapshot(_))));
+        let config = SimulationConfig { lineage: LineageConfig { log_path: Some("Cargo.toml".into()), ..default() }, ..default() };
+        assert!(matches!(config.load_lineages(None), Err(ConfigError::Lineage(LineageError::Exists(_)))));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let config = SimulationConfig { drag: 100., ..default() };
+        assert!(matches!(config.validate(), Err(ConfigError::OutOfRange { field: "drag", .. })));
+
+        let config = SimulationConfig { population: PopulationControl::Cap { max: 0 }, ..default() };
+        assert!(matches!(config.validate(), Err(ConfigError::OutOfRange { field: "population.max", .. })));
+
+        let config = SimulationConfig { mutation: MutationConfig { rate: f32::NAN, ..default() }, ..default() };
+        assert!(config.validate().is_err());
+
+        let config: SimulationConfig = ron::from_str("(food: [(shape: Bloom(center: (0.0, 0.0), sigma: 10.0, period: 5.0, duration: 6.0))])").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::OutOfRange { field: "food.shape.duration", .. })));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let uniform = FoodShape::Uniform { min: Vec2::new(-10., 0.), max: Vec2::new(10., 5.) };
+        for _ in 0..100 {
+            let p = uniform.sample(0., &mut rng);
+            assert!((-10. ..=10.).contains(&p.x) && (0. ..=5.).contains(&p.y));
+        }
+
+        let moving = FoodShape::Moving { center: Vec2::ZERO, sigma: 0., orbit: 100., period: 4. };
+        assert!(moving.sample(0., &mut rng).distance(Vec2::new(100., 0.)) < 1e-3);
+        assert!(moving.sample(1., &mut rng).distance(Vec2::new(0., 100.)) < 1e-3);
+
+        let bloom = FoodShape::Bloom { center: Vec2::ZERO, sigma: 1., period: 10., duration: 2. };
+        assert!(bloom.active(1.));
+        assert!(!bloom.active(5.));
+        assert!(bloom.active(21.));
+    }
+
+    #[test]
+    fn test_division_layout() {
+        let division = DivisionConfig { gap: 2., turn: 0., separation_speed: 10. };
+        let energies = [16., 4.];
+        let [(front, front_speed), (back, back_speed)] = division.layout(energies);
+        // the daughters just do not touch
+        assert!((front - back - (cell_radius(16.) + cell_radius(4.) + 2.)).abs() < 1e-4);
+        // and their centre of mass and momentum stay where the parent's were
+        assert!((energies[0] * front + energies[1] * back).abs() < 1e-4);
+        assert!((energies[0] * front_speed + energies[1] * back_speed).abs() < 1e-4);
+        assert!(front_speed > 0.);
+    }
+
+    #[test]
+    fn test_predation() {
+        let predation = PredationConfig::default();
+        assert!(predation.can_attack(10., 9.));
+        assert!(!predation.can_attack(10., 9.5));
+
+        // small prey is swallowed whole, whatever the mouth
+        assert_eq!(predation.bite(0.1, 10., 5., 40.), (40., true));
+        // larger prey is drained a tick at a time, never below empty
+        let (taken, engulfed) = predation.bite(0.5, 10., 8., 40.);
+        assert!(!engulfed);
+        assert!((taken - 0.5 * predation.drain_rate * FIXED_DELTA).abs() < 1e-6);
+        assert_eq!(predation.bite(1., 10., 8., 0.1), (0.1, false));
+    }
+}
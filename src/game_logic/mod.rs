@@ -2,4 +2,6 @@ pub mod cell;
 pub mod camera_controll;
 pub mod sprites;
 pub mod physics;
-pub mod math;
\ No newline at end of file
+pub mod obstacle;
+pub mod math;
+pub mod config;
\ No newline at end of file
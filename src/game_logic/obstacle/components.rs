@@ -0,0 +1,5 @@
+use bevy::prelude::*;
+
+/// A static piece of the map cells can neither enter nor see through.
+#[derive(Component, Default, Clone, Copy)]
+pub struct Obstacle;
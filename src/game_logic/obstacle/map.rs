@@ -0,0 +1,157 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Collider;
+use serde::{Serialize, Deserialize};
+
+/// Polygons enclosing less than this fraction of the square of their size are degenerate.
+const MIN_POLYGON_AREA: f32 = 1e-4;
+
+/// Obstacle outline in world coordinates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ObstacleShape {
+    Circle { center: Vec2, radius: f32 },
+    /// A closed polygon, it does not have to be convex.
+    Polygon { points: Vec<Vec2> },
+}
+impl ObstacleShape {
+    pub fn collider(&self) -> Collider {
+        match self {
+            Self::Circle { center, radius } => Collider::compound(vec![(*center, 0., Collider::ball(*radius))]),
+            Self::Polygon { points } => {
+                let n = points.len() as u32;
+                let edges: Vec<[u32; 2]> = (0..n).map(|i| [i, (i + 1) % n]).collect();
+                Collider::convex_decomposition(points, &edges)
+            },
+        }
+    }
+
+    /// Center and radius of a circle around the whole obstacle.
+    pub fn bounds(&self) -> (Vec2, f32) {
+        match self {
+            Self::Circle { center, radius } => (*center, *radius),
+            Self::Polygon { points } => {
+                let min = points.iter().copied().fold(Vec2::splat(f32::INFINITY), Vec2::min);
+                let max = points.iter().copied().fold(Vec2::splat(f32::NEG_INFINITY), Vec2::max);
+                ((min + max) / 2., (max - min).length() / 2.)
+            },
+        }
+    }
+
+    /// Area enclosed by the outline, by the shoelace formula for polygons.
+    pub fn area(&self) -> f32 {
+        match self {
+            Self::Circle { radius, .. } => std::f32::consts::PI * radius * radius,
+            Self::Polygon { points } => {
+                let twice: f32 = points.iter().zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| a.perp_dot(*b))
+                    .sum();
+                twice.abs() / 2.
+            },
+        }
+    }
+
+    pub fn translated(&self, offset: Vec2) -> Self {
+        match self {
+            Self::Circle { center, radius } => Self::Circle { center: *center + offset, radius: *radius },
+            Self::Polygon { points } => Self::Polygon { points: points.iter().map(|p| *p + offset).collect() },
+        }
+    }
+}
+
+/// All obstacles of the world, read from a RON map file.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ObstacleMap {
+    pub obstacles: Vec<ObstacleShape>,
+}
+impl ObstacleMap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| MapError::Io(path.to_path_buf(), e))?;
+        let map: Self = ron::from_str(&text)
+            .map_err(|e| MapError::Parse(path.to_path_buf(), e))?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Rejects obstacles no collider can be built for.
+    pub fn validate(&self) -> Result<(), MapError> {
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            let valid = match obstacle {
+                ObstacleShape::Circle { radius, .. } => *radius > 0.,
+                // collinear or repeated points leave nothing to build a collider from
+                ObstacleShape::Polygon { points } => points.len() >= 3
+                    && points.iter().zip(points.iter().cycle().skip(1)).all(|(a, b)| a != b)
+                    && obstacle.area() > MIN_POLYGON_AREA * (2. * obstacle.bounds().1).powi(2),
+            };
+            if !valid {
+                return Err(MapError::Invalid(i));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    /// Index of an obstacle with a non-positive radius, or a polygon enclosing no area.
+    Invalid(usize),
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "could not read map {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "could not parse map {}: {}", path.display(), e),
+            Self::Invalid(i) => write!(f, "obstacle {} of the map is degenerate", i),
+        }
+    }
+}
+impl std::error::Error for MapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let map: ObstacleMap = ron::from_str("(obstacles: [
+            Circle(center: (0.0, 100.0), radius: 50.0),
+            Polygon(points: [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (5.0, 2.0), (0.0, 10.0)]),
+        ])").unwrap();
+        assert_eq!(map.obstacles.len(), 2);
+        assert_eq!(map.obstacles[0], ObstacleShape::Circle { center: Vec2::new(0., 100.), radius: 50. });
+
+        let concave = map.obstacles[1].collider();
+        assert!(concave.contains_point(Vec2::ZERO, 0., Vec2::new(1., 5.)));
+        assert!(!concave.contains_point(Vec2::ZERO, 0., Vec2::new(5., 8.)));
+        assert!(map.obstacles[0].collider().contains_point(Vec2::ZERO, 0., Vec2::new(0., 60.)));
+
+        assert_eq!(map.obstacles[0].bounds(), (Vec2::new(0., 100.), 50.));
+        let (center, reach) = map.obstacles[1].translated(Vec2::new(-5., 0.)).bounds();
+        assert_eq!(center, Vec2::new(0., 5.));
+        assert!((reach - 50f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let valid: ObstacleMap = ron::from_str("(obstacles: [Polygon(points: [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)])])").unwrap();
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.obstacles[0].area(), 50.);
+        for text in [
+            "(obstacles: [Circle(center: (0.0, 0.0), radius: 0.0)])",
+            "(obstacles: [Polygon(points: [(0.0, 0.0), (10.0, 0.0)])])",
+            // collinear
+            "(obstacles: [Polygon(points: [(0.0, 0.0), (5.0, 5.0), (10.0, 10.0), (20.0, 20.0)])])",
+            "(obstacles: [Polygon(points: [(0.0, 0.0), (10.0, 0.0), (10.0, 0.0)])])",
+        ] {
+            let map: ObstacleMap = ron::from_str(text).unwrap();
+            assert!(matches!(map.validate(), Err(MapError::Invalid(0))), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod plugin;
+mod components;
+mod map;
+
+pub use plugin::*;
+pub use components::*;
+pub use map::*;
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::game_logic::config::{SimulationConfig, WorldShape};
+use super::*;
+
+pub struct ObstaclePlugin;
+impl Plugin for ObstaclePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ObstacleMap>()
+            .add_systems(Startup, obstacle_setup);
+    }
+}
+
+/// Spawns the obstacles of the map `main` loaded, see [`SimulationConfig::load_map`].
+pub fn obstacle_setup(
+    mut commands: Commands,
+    map: Res<ObstacleMap>,
+    config: Res<SimulationConfig>,
+) {
+    if let Some(path) = &config.map {
+        info!("Loaded {} obstacles from {}", map.obstacles.len(), path.display());
+    }
+    place_obstacles(&mut commands, &map.obstacles, &config.world.shape);
+}
+
+/// Spawns `obstacles` into `world`. Server and clients place them the same way.
+pub fn place_obstacles(
+    commands: &mut Commands,
+    obstacles: &[ObstacleShape],
+    world: &WorldShape,
+) {
+    for shape in obstacles.iter() {
+        // on a torus cells find obstacles across the seam like they find each other,
+        // only the outline has to be drawn again wherever it sticks out of the world
+        let shape = match world {
+            WorldShape::Torus { .. } => {
+                let (center, _) = shape.bounds();
+                shape.translated(world.contain(center) - center)
+            },
+            _ => shape.clone(),
+        };
+        spawn_obstacle(commands, &shape);
+        let (center, reach) = shape.bounds();
+        for shift in world.shifts(center, reach).into_iter().skip(1) {
+            commands.spawn(obstacle_outline(&shape.translated(shift)));
+        }
+    }
+}
+
+pub fn spawn_obstacle(
+    commands: &mut Commands,
+    shape: &ObstacleShape,
+) -> Entity {
+    commands.spawn((
+        Obstacle,
+        shape.collider(),
+        obstacle_outline(shape),
+    )).id()
+}
+
+fn obstacle_outline(shape: &ObstacleShape) -> (ShapeBundle, Fill) {
+    let path = match shape {
+        ObstacleShape::Circle { center, radius } => GeometryBuilder::build_as(&shapes::Circle {
+            radius: *radius,
+            center: *center,
+        }),
+        ObstacleShape::Polygon { points } => GeometryBuilder::build_as(&shapes::Polygon {
+            points: points.clone(),
+            closed: true,
+        }),
+    };
+    (
+        ShapeBundle {
+            path,
+            spatial: SpatialBundle::from_transform(Transform::from_xyz(0., 0., -5.)),
+            ..default()
+        },
+        Fill::color(Color::hex("3a3a40").unwrap()),
+    )
+}
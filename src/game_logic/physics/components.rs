@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
 
 #[derive(Bundle, Default)]
 pub struct PhysicsBundle {
@@ -13,13 +14,13 @@ impl PhysicsBundle {
     }
 }
 
-#[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct Velocity(pub Vec2);
 
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
 pub struct Force(pub Vec2);
 
-#[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct AngularVelocity(pub f32);
 
 #[derive(Component, Deref, DerefMut, Default, Clone, Copy)]
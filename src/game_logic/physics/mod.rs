@@ -1,5 +1,6 @@
 mod physics;
 mod components;
+mod world;
 
 pub use physics::*;
 pub use components::*;
\ No newline at end of file
@@ -1,18 +1,12 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::{Collider, RapierContext, QueryFilter};
 
-use crate::game_logic::{cell::*, math::quat_to_direction};
+use crate::game_logic::{cell::*, config::SimulationConfig, math::quat_to_direction, obstacle::Obstacle};
 use super::*;
 
 const MASS_MULTIPLIER: f32 = 1./200.;
 const RADIUS_MULTIPLIER: f32 = 1./50.;
 
-pub const PLAYER_SPEED: f32 = 500.;
-pub const PLAYER_ANGLE_SPEED: f32 = 7.;
-
-pub const DRAG: f32 = 2.;
-pub const ANGULAR_DRAG: f32 = 2.;
-
 pub struct PhysicsPlugin;
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
@@ -20,45 +14,61 @@ impl Plugin for PhysicsPlugin {
             .add_systems(FixedUpdate, (
                 flagellum_physics,
                 cell_push,
+                obstacle_push,
                 velocity_update,
                 angular_update,
             ));
     }
 }
 
+#[inline]
+pub fn cell_mass(energy: f32, chloroplasts: u8, config: &SimulationConfig) -> f32 {
+    energy * MASS_MULTIPLIER + chloroplasts as f32 * config.chloroplast_mass
+}
+
 pub fn velocity_update(
-    mut query: Query<(&mut Transform, &mut Velocity, &mut Force, &Energy)>
+    mut query: Query<(&mut Transform, &mut Velocity, &mut Force, &Energy, &Genome, &Radius)>,
+    config: Res<SimulationConfig>,
 ) {
     query
         .par_iter_mut()
-        .for_each_mut(|(mut transform, mut velocity, mut force, energy)| {
+        .for_each_mut(|(mut transform, mut velocity, mut force, energy, genome, radius)| {
             //get current acceleration
-            let mass = **energy * MASS_MULTIPLIER;
+            let mass = cell_mass(**energy, genome.chloroplasts, &config);
             let acceleration = **force * FIXED_DELTA / mass;
             **force = Vec2::ZERO;
 
-            let multiplier = 1. - DRAG * FIXED_DELTA;
+            let multiplier = 1. - config.drag * FIXED_DELTA;
             
             //update velocity
             **velocity += acceleration/2.;
             **velocity *= multiplier;
             transform.translation += Vec3::from((**velocity * FIXED_DELTA, 0.));
             **velocity += acceleration/2.;
+
+            //keep inside the world, bouncing off walls
+            let mut position = transform.translation.truncate();
+            if let Some(normal) = config.world.shape.confine(&mut position, **radius) {
+                let towards_wall = velocity.dot(normal).min(0.);
+                **velocity -= (1. + config.world.wall_bounce) * towards_wall * normal;
+            }
+            transform.translation = position.extend(transform.translation.z);
         });
 }
 
 pub fn angular_update(
-    mut query: Query<(&mut Transform, &mut AngularVelocity, &mut AngularForce, &Energy)>,
+    mut query: Query<(&mut Transform, &mut AngularVelocity, &mut AngularForce, &Energy, &Genome)>,
+    config: Res<SimulationConfig>,
 ) {
     query
         .par_iter_mut()
-        .for_each_mut(|(mut transform, mut velocity, mut force, energy)| {
+        .for_each_mut(|(mut transform, mut velocity, mut force, energy, genome)| {
             //get current angular acceleration
-            let mass = **energy * MASS_MULTIPLIER;
+            let mass = cell_mass(**energy, genome.chloroplasts, &config);
             let acceleration = **force * FIXED_DELTA / mass;
             **force = 0.;
     
-            let multiplier = 1. - ANGULAR_DRAG * FIXED_DELTA;
+            let multiplier = 1. - config.angular_drag * FIXED_DELTA;
             
             //update angular velocity
             **velocity += acceleration/2.;
@@ -70,15 +80,16 @@ pub fn angular_update(
 
 pub fn flagellum_physics(
     mut cell_query: Query<(&CellFlagella, &mut Force, &mut AngularForce, &Transform, &Radius)>,
-    flag_query: Query<(&Activation, &Angle, &Transform), With<Flagellum>>
+    flag_query: Query<(&Activation, &Angle, &Transform), With<Flagellum>>,
+    config: Res<SimulationConfig>,
 ) {
     cell_query
         .par_iter_mut()
         .for_each_mut(|(flagella, mut force, mut angular_force, cell_transform, radius)| {
             for flagellum_entity in flagella.iter() {
                 if let Ok((activation, angle, flagellum_transform)) = flag_query.get(*flagellum_entity) {
-                    **force += **activation * quat_to_direction(cell_transform.rotation * flagellum_transform.rotation) * PLAYER_SPEED;
-                    **angular_force -= **activation * angle.sin() * **radius * RADIUS_MULTIPLIER * PLAYER_ANGLE_SPEED;
+                    **force += **activation * quat_to_direction(cell_transform.rotation * flagellum_transform.rotation) * config.player_speed;
+                    **angular_force -= **activation * angle.sin() * **radius * RADIUS_MULTIPLIER * config.player_angle_speed;
                 }
             }
         });
@@ -89,35 +100,78 @@ pub fn cell_push(
     mut cell_query: Query<(Entity, &Transform, &Radius, &CellCollider, &mut Force), With<Cell>>,
     cell_b_query: Query<(&Transform, &Radius)>,
     rapier_context: Res<RapierContext>,
+    config: Res<SimulationConfig>,
 ) {
     cell_query
         .par_iter_mut()
         .for_each_mut(|(entity, transform_a, radius_a, cell_collider, mut force)| {
             if let Ok((_, collider)) = collider_query.get(**cell_collider) {
-                rapier_context.intersections_with_shape(
-                    transform_a.translation.truncate(), 
-                    0., 
-                    collider, 
-                    QueryFilter::default(), 
-                    |x| {
-                        if let Ok((parent, _)) = collider_query.get(x) {
-                            if parent.get() == entity {
-                                return true;
+                // on a torus the cell is also looked for at its images across the seam
+                for shift in config.world.shape.shifts(transform_a.translation.truncate(), **radius_a) {
+                    let position_a = transform_a.translation.truncate() + shift;
+                    rapier_context.intersections_with_shape(
+                        position_a, 
+                        0., 
+                        collider, 
+                        QueryFilter::default(), 
+                        |x| {
+                            if let Ok((parent, _)) = collider_query.get(x) {
+                                if parent.get() == entity {
+                                    return true;
+                                }
+                                if let Ok((transform_b, radius_b)) = cell_b_query.get(parent.get()) {
+                                    let mut direction = position_a - transform_b.translation.truncate();
+                                    let d = direction.length();
+                                    direction = match direction.try_normalize() {
+                                        Some(d) => d,
+                                        None => quat_to_direction(transform_a.rotation),
+                                    };
+                                    let magnitude = (radius_a.0 + radius_b.0 - d) * config.intercell_push;
+                                    **force += magnitude * direction;
+                                }
                             }
-                            if let Ok((transform_b, radius_b)) = cell_b_query.get(parent.get()) {
-                                let mut direction = transform_a.translation.truncate() - transform_b.translation.truncate();
-                                let d = direction.length();
-                                direction = match direction.try_normalize() {
-                                    Some(d) => d,
-                                    None => quat_to_direction(transform_a.rotation),
+                            true
+                        }
+                    );
+                }
+            }
+    });
+}
+
+pub fn obstacle_push(
+    collider_query: Query<&Collider, With<CellColliderTag>>,
+    obstacle_query: Query<&Collider, With<Obstacle>>,
+    mut cell_query: Query<(&Transform, &Radius, &CellCollider, &mut Force), With<Cell>>,
+    rapier_context: Res<RapierContext>,
+    config: Res<SimulationConfig>,
+) {
+    cell_query
+        .par_iter_mut()
+        .for_each(|(transform, radius, cell_collider, mut force)| {
+            if let Ok(collider) = collider_query.get(**cell_collider) {
+                for shift in config.world.shape.shifts(transform.translation.truncate(), **radius) {
+                    let center = transform.translation.truncate() + shift;
+                    rapier_context.intersections_with_shape(
+                        center,
+                        0.,
+                        collider,
+                        QueryFilter::default(),
+                        |x| {
+                            if let Ok(obstacle) = obstacle_query.get(x) {
+                                // obstacles are placed in world coordinates
+                                let projection = obstacle.project_point(Vec2::ZERO, 0., center, false);
+                                let outwards = center - projection.point;
+                                let distance = outwards.length();
+                                let (direction, depth) = match projection.is_inside {
+                                    true => (-outwards.normalize_or_zero(), **radius + distance),
+                                    false => (outwards.normalize_or_zero(), **radius - distance),
                                 };
-                                let magnitude = (radius_a.0 + radius_b.0 - d) * INTERCELL_PUSH;
-                                **force += magnitude * direction;
+                                **force += depth.max(0.) * config.obstacle_push * direction;
                             }
+                            true
                         }
-                        true
-                    }
-                );
+                    );
+                }
             }
-    });
-}
\ No newline at end of file
+        });
+}
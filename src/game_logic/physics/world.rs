@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::game_logic::config::WorldShape;
+
+impl WorldShape {
+    /// Moves a body of `radius` at `position` back inside the world, wrapping it around
+    /// a torus. Returns the inward normal of the wall it was pushed away from.
+    pub fn confine(&self, position: &mut Vec2, radius: f32) -> Option<Vec2> {
+        match *self {
+            Self::Unbounded => None,
+            Self::Rect { half_size } => {
+                let limit = (half_size - radius).max(Vec2::ZERO);
+                let clamped = position.clamp(-limit, limit);
+                let normal = Vec2::select(clamped.cmpne(*position), (clamped - *position).signum(), Vec2::ZERO);
+                *position = clamped;
+                normal.try_normalize()
+            },
+            Self::Circle { radius: world_radius } => {
+                let limit = (world_radius - radius).max(0.);
+                if position.length() <= limit {
+                    return None;
+                }
+                let normal = -position.normalize_or_zero();
+                *position = -normal * limit;
+                Some(normal)
+            },
+            Self::Torus { half_size } => {
+                *position = wrap(*position, half_size);
+                None
+            },
+        }
+    }
+
+    /// Moves a point into the world, used for things that are placed rather than moved.
+    pub fn contain(&self, mut position: Vec2) -> Vec2 {
+        self.confine(&mut position, 0.);
+        position
+    }
+
+    /// Offsets at which a spatial query reaching `reach` around `position` has to be
+    /// repeated to also find what lies across the seam, as often as the reach spans the
+    /// world. The first one is always zero.
+    pub fn shifts(&self, position: Vec2, reach: f32) -> Vec<Vec2> {
+        let Self::Torus { half_size } = *self else {
+            return vec![Vec2::ZERO];
+        };
+        let axis = |p: f32, half: f32| {
+            let size = 2. * half;
+            let first = ((-half - p - reach) / size).ceil() as i32;
+            let last = ((half - p + reach) / size).floor() as i32;
+            let mut shifts = vec![0.];
+            shifts.extend((first..=last).filter(|k| *k != 0).map(|k| k as f32 * size));
+            shifts
+        };
+        let xs = axis(position.x, half_size.x);
+        let ys = axis(position.y, half_size.y);
+        ys.iter().flat_map(|y| xs.iter().map(move |x| Vec2::new(*x, *y))).collect()
+    }
+
+    /// Shortest displacement from `from` to `to`, across the seam if that is closer.
+    pub fn offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        match *self {
+            Self::Torus { half_size } => wrap(to - from, half_size),
+            _ => to - from,
+        }
+    }
+}
+
+fn wrap(position: Vec2, half_size: Vec2) -> Vec2 {
+    Vec2::new(
+        (position.x + half_size.x).rem_euclid(2. * half_size.x) - half_size.x,
+        (position.y + half_size.y).rem_euclid(2. * half_size.y) - half_size.y,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walls() {
+        let rect = WorldShape::Rect { half_size: Vec2::new(100., 50.) };
+        let mut position = Vec2::new(120., 0.);
+        assert_eq!(rect.confine(&mut position, 10.), Some(Vec2::new(-1., 0.)));
+        assert_eq!(position, Vec2::new(90., 0.));
+        assert_eq!(rect.confine(&mut position, 10.), None);
+
+        let circle = WorldShape::Circle { radius: 100. };
+        let mut position = Vec2::new(0., -200.);
+        assert_eq!(circle.confine(&mut position, 0.), Some(Vec2::new(0., 1.)));
+        assert_eq!(position, Vec2::new(0., -100.));
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = WorldShape::Torus { half_size: Vec2::new(100., 100.) };
+        let mut position = Vec2::new(110., -250.);
+        assert_eq!(torus.confine(&mut position, 10.), None);
+        assert!(position.distance(Vec2::new(-90., -50.)) < 1e-4);
+
+        assert_eq!(torus.shifts(Vec2::ZERO, 10.), vec![Vec2::ZERO]);
+        assert_eq!(torus.shifts(Vec2::new(95., -95.), 10.).len(), 4);
+        // a reach wider than the world needs several images per side
+        let shifts = torus.shifts(Vec2::new(50., 0.), 450.);
+        assert_eq!(shifts[0], Vec2::ZERO);
+        let xs: Vec<f32> = shifts.iter().filter(|s| s.y == 0.).map(|s| s.x).collect();
+        assert_eq!(xs, vec![0., -600., -400., -200., 200., 400.]);
+        assert_eq!(shifts.len(), 6 * 5);
+
+        assert!(torus.offset(Vec2::new(90., 0.), Vec2::new(-90., 50.)).distance(Vec2::new(20., 50.)) < 1e-4);
+        assert_eq!(WorldShape::Unbounded.offset(Vec2::new(90., 0.), Vec2::new(-90., 0.)), Vec2::new(-180., 0.));
+        assert_eq!(WorldShape::Unbounded.shifts(Vec2::new(1e9, 0.), 10.), vec![Vec2::ZERO]);
+    }
+}
@@ -17,6 +17,7 @@ impl Plugin for SpritesPlugin {
                 animate_sprite,
                 flagellum_animation_speed,
                 eye_focus_animation,
+                light_glow,
             ));
     }
 }
@@ -7,13 +7,33 @@ use bevy::log::LogPlugin;
 use communication::server::ServerPlugin;
 use game_logic::cell::*;
 use game_logic::physics::*;
+use game_logic::config::*;
+use game_logic::obstacle::*;
 
 use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 fn main() {
-    App::new()
+    let (map, snapshot, lineages, config) = SimulationConfig::from_args()
+        .and_then(|config| {
+            let snapshot = config.load_snapshot()?;
+            let lineages = config.load_lineages(snapshot.as_ref().map(|s| s.tick))?;
+            Ok((config.load_map()?, snapshot, lineages, config))
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    let mut app = App::new();
+    if let Some(snapshot) = snapshot {
+        app.insert_resource(snapshot);
+    }
+    app
+        .insert_resource(config)
+        .insert_resource(map)
+        .insert_resource(lineages)
         .add_plugins((
             MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1./60.))),
             LogPlugin::default(),
@@ -21,6 +41,7 @@ fn main() {
             CellCorePlugin,
             CellServerPlugin,
             PhysicsPlugin,
+            ObstaclePlugin,
             ServerPlugin,
         ))    
         .run();